@@ -1,29 +1,64 @@
 use egui::load::SizedTexture;
-use egui::{Color32, ColorImage, ImageData, ImageSource, RichText, TextureHandle, TextureOptions};
-use gilrs::Gilrs;
+use egui::{
+    Color32, ColorImage, ImageData, ImageSource, KeyboardShortcut, RichText, TextureHandle,
+};
 use serde::{Deserialize, Serialize};
-use solgb::{self, Channel, GameboyType, PaletteColors};
+use solgb::{self, AudioControl, CartType, Channel, GameboyType, PaletteColors};
 use solgb::{Gameboy, RomInfo};
-use std::cell::RefCell;
-use std::collections::VecDeque;
+use std::cell::{OnceCell, RefCell};
+use std::collections::{BTreeMap, VecDeque};
 use std::ops::RangeInclusive;
+use std::path::PathBuf;
 use std::rc::Rc;
 use std::sync::Arc;
+use std::time::Duration;
 #[cfg(not(target_arch = "wasm32"))]
-use std::time::{Duration, Instant};
+use std::time::Instant;
 #[cfg(target_arch = "wasm32")]
 use web_time::Instant;
 
-use crate::audio::Audio;
-use crate::input::{Inputs, InputsState};
+use crossbeam_channel::{TryRecvError, TrySendError};
+
+use crate::audio::{Audio, Latency};
+use crate::display::{correct_cgb_color, ColorAdjust, DisplayOptions};
+use crate::input::{init_gilrs, Autofire, GBButton, Hotkey, Hotkeys, Inputs, InputsState, Player};
+use crate::notifications::Notifications;
 use crate::palettes::Palettes;
 use crate::saves::Saves;
+use crate::stats::Stats;
+use crate::touch::TouchControls;
+
+//Also decides where eframe (and native saves) keep their files
+pub const APP_NAME: &str = "solgb";
+//Shown in the window title, followed by the game when one is running
+const WINDOW_TITLE: &str = "solgb";
 
 pub const WIDTH: usize = solgb::SCREEN_WIDTH as usize;
 pub const HEIGHT: usize = solgb::SCREEN_HEIGHT as usize;
 
-pub const DMG_ROM_NAME: &str = "_DMGBOOTROM";
-pub const CGB_ROM_NAME: &str = "_CGBBOOTROM";
+//Everything we keep in save storage that isn't a save starts with this so the save manager can skip it
+pub(crate) const INTERNAL_PREFIX: &str = "__solgb_";
+pub const DMG_ROM_NAME: &str = "__solgb_DMGBOOTROM";
+pub const CGB_ROM_NAME: &str = "__solgb_CGBBOOTROM";
+
+pub(crate) const ROM_FILTER: (&str, &[&str]) =
+    ("Gameboy / Gameboy Color Rom", &["gb", "gbc", "zip"]);
+pub(crate) const ALL_FILES: (&str, &[&str]) = ("All Files", &["*"]);
+const PALETTE_FILTER: (&str, &[&str]) = ("Palette Pack", &["json"]);
+
+//How many roms are kept in the recent list
+const MAX_RECENT_ROMS: usize = 10;
+//Slow motion only, the emulator can't go faster than the audio pulls samples. It's paced by the
+//audio too, so slowing down how often update takes frames would just back them up. Holding each
+//sample for longer slows the game itself, at the cost of lowering the pitch
+const SPEEDS: [(u8, &str); 3] = [(25, "0.25x"), (50, "0.5x"), (100, "1x")];
+//Web keeps recent roms in storage under this prefix so they can be opened again without picking a file
+pub(crate) const RECENT_ROM_PREFIX: &str = "__solgb_ROM_";
+
+//How long on screen indicators (like the active palette) stay visible
+const INDICATOR_DURATION: Duration = Duration::from_secs(2);
+//How often to update when there's no game running, egui still repaints right away on input
+const IDLE_REPAINT: Duration = Duration::from_millis(100);
 
 /// We derive Deserialize/Serialize so we can persist app state on shutdown.
 #[derive(serde::Deserialize, serde::Serialize)]
@@ -33,50 +68,164 @@ pub struct TemplateApp {
     gameboy: Option<Gameboy>,
     #[serde(skip)]
     gb_texture: Option<TextureHandle>,
+    //What gets uploaded to gb_texture, kept around so each frame can reuse its pixels
+    #[serde(skip)]
+    frame_image: Arc<ColorImage>,
+    #[serde(skip)]
+    last_frame: Option<Vec<u32>>,
+    #[serde(skip)]
+    rom: Option<Vec<u8>>,
+    #[serde(skip)]
+    emulator_error: Option<String>,
+    #[serde(skip)]
+    paused: bool,
+    //Set when paused by handle_focus, so it only resumes what it paused itself
+    #[serde(skip)]
+    focus_paused: bool,
+    //Running just long enough to get the next frame, then pausing again
+    #[serde(skip)]
+    advancing: bool,
+    //Percent of normal speed, one of SPEEDS
+    #[serde(skip)]
+    speed: u8,
+    speed_visible: bool,
+    //An uploaded save that doesn't match the running game's save ram size, waiting on the user
+    #[serde(skip)]
+    pending_save: Option<PendingSave>,
+    #[serde(skip)]
+    pending_zip: Option<PendingZip>,
+    //Whether the running game is in GBC mode, color correction only applies then
+    #[serde(skip)]
+    cgb_mode: bool,
     #[serde(skip)]
     audio: Audio,
     #[serde(skip)]
     last_save: Instant,
     #[serde(skip)]
+    last_update: Instant,
+    #[serde(skip)]
     saves: Option<Saves>,
     #[serde(skip)]
     events: Events,
     #[serde(skip)]
+    notifications: Notifications,
+    #[serde(skip)]
     inputs: Option<Inputs>,
+    #[serde(skip)]
+    palette_indicator: Option<(String, Instant)>,
+    //Set when the running ROM's header checksum is wrong, until dismissed
+    #[serde(skip)]
+    rom_warning: Option<String>,
+    //Worked out the first time the ROM info window shows it, since it reads the whole ROM
+    #[serde(skip)]
+    global_checksum: OnceCell<Option<bool>>,
+    #[serde(skip)]
+    hotkey_rebinding: Option<Hotkey>,
+    #[serde(skip)]
+    window_title: String,
+    #[serde(skip)]
+    button_rebinding: Option<GBButton>,
+    #[serde(skip)]
+    last_catch_up: Option<Instant>,
+    #[serde(skip)]
+    dropped_frames: u64,
+    #[serde(skip)]
+    menu_chord_held: bool,
+    #[cfg(all(feature = "ipc", not(target_arch = "wasm32")))]
+    #[serde(skip)]
+    ipc: Option<crate::ipc::Ipc>,
     volume: Volume,
     saves_visible: bool,
+    rom_info_visible: bool,
     bootrom_options: BootRomOptions,
+    last_dirs: LastDirs,
+    settings: Settings,
+    display: DisplayOptions,
+    color_adjust: ColorAdjust,
+    stats: Stats,
     palettes: Palettes,
     inputs_visible: bool,
     input_state: InputsState,
+    //Only edited for now, nothing reads it until there's a second gameboy to drive
+    input_state_p2: InputsState,
+    #[serde(skip)]
+    inputs_p2: Option<Inputs>,
+    #[serde(skip)]
+    bindings_player: Player,
+    hotkeys: Hotkeys,
+    autofire: Autofire,
     input_touch: [bool; 8],
     menu_visible: bool,
     touch_visible: bool,
+    touch: TouchControls,
+    //Seconds each game (by name) has been running for
+    play_time: BTreeMap<String, f64>,
+    recent_roms: VecDeque<RecentRom>,
 }
 
 impl Default for TemplateApp {
     fn default() -> Self {
         let events = Events::default();
-        let audio = Audio::new();
+        let notifications = Notifications::default();
+        let audio = Audio::new(None, Latency::default());
         // let stream = audio.get_stream();
         Self {
             gameboy: None,
             gb_texture: None,
+            frame_image: Arc::new(ColorImage::new([WIDTH, HEIGHT], Color32::BLACK)),
+            last_frame: None,
+            rom: None,
+            emulator_error: None,
+            paused: false,
+            focus_paused: false,
+            advancing: false,
+            speed: 100,
+            speed_visible: false,
+            pending_save: None,
+            pending_zip: None,
+            cgb_mode: false,
             audio,
             // stream: None,
             last_save: Instant::now(),
-            saves: Saves::new(events.clone()),
+            last_update: Instant::now(),
+            saves: Saves::new(events.clone(), notifications.clone()),
             events,
+            notifications,
             inputs: None,
+            palette_indicator: None,
+            rom_warning: None,
+            global_checksum: OnceCell::new(),
+            hotkey_rebinding: None,
+            window_title: String::new(),
+            button_rebinding: None,
+            last_catch_up: None,
+            dropped_frames: 0,
+            menu_chord_held: false,
+            #[cfg(all(feature = "ipc", not(target_arch = "wasm32")))]
+            ipc: None,
             volume: Volume::default(),
             saves_visible: false,
+            rom_info_visible: false,
             bootrom_options: BootRomOptions::new(),
+            last_dirs: LastDirs::default(),
+            settings: Settings::default(),
+            display: DisplayOptions::default(),
+            color_adjust: ColorAdjust::default(),
+            stats: Stats::default(),
             palettes: Palettes::new(),
             inputs_visible: false,
             input_state: InputsState::default(),
+            input_state_p2: InputsState::unbound(),
+            inputs_p2: None,
+            bindings_player: Player::One,
+            hotkeys: Hotkeys::default(),
+            autofire: Autofire::default(),
             input_touch: [false; 8],
             menu_visible: true,
             touch_visible: false,
+            touch: TouchControls::default(),
+            play_time: BTreeMap::new(),
+            recent_roms: VecDeque::new(),
         }
     }
 }
@@ -84,6 +233,40 @@ impl Default for TemplateApp {
 impl TemplateApp {
     /// Called once before the first frame.
     pub fn new(cc: &eframe::CreationContext<'_>) -> Self {
+        let mut app = Self::restore(cc);
+        //Recent roms point at storage keys that may have been renamed by Saves::new
+        #[cfg(target_arch = "wasm32")]
+        for recent in &mut app.recent_roms {
+            if let Some(key) = crate::saves::renamed_key(&recent.location.to_string_lossy()) {
+                recent.location = PathBuf::from(key);
+            }
+        }
+        if app.volume.output_device.is_some() || app.volume.latency != Latency::default() {
+            app.audio = Audio::new(app.volume.output_device.as_deref(), app.volume.latency);
+        }
+
+        #[cfg(all(feature = "ipc", not(target_arch = "wasm32")))]
+        {
+            app.ipc = crate::ipc::Ipc::start(cc.egui_ctx.clone());
+        }
+
+        app
+    }
+
+    /// Queues up the ROM at `path` to be loaded on the next update, showing the menu if it can't be read.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn open_path(&mut self, path: PathBuf) {
+        match std::fs::read(&path) {
+            Ok(data) => self.events.push(Event::OpenRom(data, Some(path))),
+            Err(err) => {
+                self.notifications
+                    .error(format!("Unable to read ROM {}: {err}", path.display()));
+                self.menu_visible = true;
+            }
+        }
+    }
+
+    fn restore(cc: &eframe::CreationContext<'_>) -> Self {
         // This is also where you can customize the look and feel of egui using
         // `cc.egui_ctx.set_visuals` and `cc.egui_ctx.set_fonts`.
 
@@ -105,88 +288,86 @@ impl TemplateApp {
     }
 
     fn load(&mut self) {
+        //The first filter is the one the dialog starts with
+        let filter = if self.settings.all_files_default {
+            [ALL_FILES, ROM_FILTER]
+        } else {
+            [ROM_FILTER, ALL_FILES]
+        };
         open(
             &self.events,
-            &[
-                (("Gameboy Rom"), &["gb", "gbc"]),
-                ("Gameboy Color Rom", &["gb", "gbc"]),
-            ],
+            &filter,
             EventType::OpenRom,
+            &mut self.last_dirs,
         );
     }
 
     fn handle_custom_events(&mut self) {
         match self.events.get_next() {
-            Some(Event::OpenRom(rom)) => {
-                let (name, rom_type) = if let Ok(rom_info) = RomInfo::new(&rom) {
-                    (rom_info.get_name(), *rom_info.get_type())
-                } else {
-                    log::error!("ROM does not appear to be a gameboy game");
-                    return;
-                };
-
-                log::info!("Loading ROM: {name}");
-
-                if let Some(saves) = &mut self.saves {
-                    saves.setup_saveram(&name);
-                    let boot_rom = saves.load_bootrom(&rom_type, &self.bootrom_options);
-
-                    let pal = self.palettes.get_u32_palette();
-                    let palette = PaletteColors::new((pal[0], pal[1], pal[2]));
-
-                    let mut gameboy = match solgb::GameboyBuilder::default()
-                        .with_rom(&rom)
-                        .with_model(self.bootrom_options.gb_type)
-                        .with_exram(saves.save_ram.clone())
-                        .with_boot_rom(boot_rom)
-                        .with_palette(Some(palette))
-                        .build()
-                    {
-                        Ok(gameboy) => gameboy,
-                        Err(err) => {
-                            log::error!("Unable to setup gameboy: {err}");
-                            saves.set_rom_info(None);
-                            return;
-                        }
-                    };
-
-                    self.audio = Audio::new();
-
-                    self.audio.set_volume(self.volume.master as u8);
-                    gameboy
-                        .audio_control
-                        .set_volume(Channel::Square1, self.volume.square_1 as f32);
-                    gameboy
-                        .audio_control
-                        .set_volume(Channel::Square2, self.volume.square_2 as f32);
-                    gameboy
-                        .audio_control
-                        .set_volume(Channel::Wave, self.volume.wave as f32);
-                    gameboy
-                        .audio_control
-                        .set_volume(Channel::Noise, self.volume.noise as f32);
-
-                    saves.set_rom_info(Some(gameboy.rom_info.clone()));
-
-                    self.audio.set_audio_control(gameboy.audio_control.clone());
-                    self.audio.play();
-
-                    match gameboy.start() {
-                        Ok(_) => log::info!("Emulation started"),
-                        Err(error) => log::error!("Failed to start running emulation: {error}"),
-                    };
-
-                    self.gameboy.replace(gameboy);
-
-                    self.menu_visible = false;
+            Some(Event::OpenRom(rom, path)) if crate::rom::is_zip(&rom) => {
+                match crate::rom::unzip_roms(&rom) {
+                    Ok(mut roms) if roms.len() == 1 => {
+                        let (_name, rom) = roms.remove(0);
+                        self.events.push(Event::OpenRom(rom, path));
+                    }
+                    Ok(roms) if !roms.is_empty() => {
+                        self.pending_zip = Some(PendingZip { roms, path });
+                    }
+                    Ok(_) => {
+                        self.notifications
+                            .error("The zip file doesn't contain a .gb or .gbc ROM");
+                        self.menu_visible = true;
+                    }
+                    Err(err) => {
+                        self.notifications
+                            .error(format!("Unable to read zip file: {err}"));
+                        self.menu_visible = true;
+                    }
+                }
+            }
+            Some(Event::OpenRom(rom, path)) => {
+                self.load_rom(rom, false);
+                if self.gameboy.is_some() {
+                    self.remember_rom(path);
                 }
             }
             Some(Event::SaveUpload(name, data)) => {
-                if let Some(saves) = &mut self.saves {
-                    saves.save(&name, &data);
+                //Only the running game's cartridge says how big its save should be
+                let running = self.gameboy.as_ref().is_some_and(|gameboy| {
+                    gameboy.rom_info.get_name() == name.strip_suffix(".sav").unwrap_or(&name)
+                });
+                let expected = self
+                    .rom
+                    .as_deref()
+                    .filter(|_| running)
+                    .and_then(crate::rom::ram_size);
+                match expected {
+                    Some(expected) if expected != data.len() => {
+                        self.pending_save = Some(PendingSave {
+                            name,
+                            data,
+                            expected,
+                        })
+                    }
+                    _ => {
+                        if let Some(saves) = &mut self.saves {
+                            saves.save(&name, &data);
+                        }
+                    }
                 }
             }
             Some(Event::BootromUpload(br_type, data)) => {
+                match crate::rom::check_boot_rom(br_type, &data) {
+                    Ok(true) => (),
+                    Ok(false) => {
+                        self.notifications
+                            .warn("Boot ROM isn't a known dump, using it anyway");
+                    }
+                    Err(err) => {
+                        self.notifications.error(err);
+                        return;
+                    }
+                }
                 if let Some(saves) = &mut self.saves {
                     match br_type {
                         GameboyType::DMG => saves.save(DMG_ROM_NAME, &data),
@@ -194,102 +375,813 @@ impl TemplateApp {
                     }
                 }
             }
-            Some(Event::Resume) => self.audio.play(),
+            Some(Event::PaletteUpload(data)) => match self.palettes.import(&data) {
+                Ok(added) => {
+                    self.notifications
+                        .info(format!("Imported {added} palettes"));
+                }
+                Err(err) => {
+                    self.notifications
+                        .error(format!("Unable to import palettes: {err}"));
+                }
+            },
+            Some(Event::SaveZipUpload(data)) => {
+                if let Some(saves) = &mut self.saves {
+                    saves.import_zip(&data);
+                }
+            }
+            Some(Event::Resume) => {
+                if !self.paused {
+                    self.audio.play()
+                }
+            }
             None => (),
         }
     }
 
-    fn display_inputs(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
-        let inputs = self.inputs.get_or_insert_with(|| {
-            Inputs::with_state(Gilrs::new().unwrap(), ctx.clone(), self.input_state.clone())
-        });
-        ui.horizontal(|ui| {
-            ui.monospace("A:        ".to_string());
-            if ui
-                .text_edit_singleline(&mut inputs.a.to_string())
-                .has_focus()
-            {
-                inputs.update_buttons(crate::input::GBButton::A);
-                self.input_state = inputs.save();
+    //keep_save_ram reuses the save ram already in Saves rather than reading it back from storage
+    fn load_rom(&mut self, rom: Vec<u8>, keep_save_ram: bool) {
+        let (name, rom_type) = if let Ok(rom_info) = RomInfo::new(&rom) {
+            (rom_info.get_name(), *rom_info.get_type())
+        } else {
+            self.notifications
+                .error("This file doesn't look like a Game Boy ROM");
+            self.menu_visible = true;
+            return;
+        };
+
+        let Some(saves) = &mut self.saves else {
+            return;
+        };
+        log::info!("Loading ROM: {name}");
+
+        //The running game is left alone until the new one is built, so a failed build doesn't lose it
+        let save_ram = match keep_save_ram {
+            true => saves.save_ram.clone(),
+            false => saves.read_save_ram(&name),
+        };
+        let boot_rom = saves.load_bootrom(&rom_type, &self.bootrom_options);
+        let previous = self
+            .gameboy
+            .as_ref()
+            .map(|gameboy| gameboy.rom_info.get_name());
+        self.palettes.rom_unloaded();
+        self.palettes.rom_loaded(&name);
+
+        let pal = self.palettes.get_u32_palette();
+        let palette = PaletteColors::new((pal[0], pal[1], pal[2]));
+
+        let mut gameboy = match solgb::GameboyBuilder::default()
+            .with_rom(&rom)
+            .with_model(self.bootrom_options.gb_type)
+            .with_exram(save_ram.clone())
+            .with_boot_rom(boot_rom)
+            .with_palette(Some(palette))
+            .build()
+        {
+            Ok(gameboy) => gameboy,
+            Err(err) => {
+                self.notifications
+                    .error(format!("Unable to start the game: {err}"));
+                //Back to the running game's palette
+                self.palettes.rom_unloaded();
+                if let Some(previous) = &previous {
+                    self.palettes.rom_loaded(previous);
+                }
+                return;
             }
-        });
-        ui.horizontal(|ui| {
-            ui.monospace("B:        ".to_string());
-            if ui
-                .text_edit_singleline(&mut inputs.b.to_string())
-                .has_focus()
-            {
-                inputs.update_buttons(crate::input::GBButton::B);
-                self.input_state = inputs.save();
+        };
+
+        self.unload_rom();
+        self.palettes.rom_loaded(&name);
+        let Some(saves) = &mut self.saves else {
+            return;
+        };
+        saves.save_ram = save_ram;
+
+        self.audio = Audio::new(self.volume.output_device.as_deref(), self.volume.latency);
+
+        self.audio.set_volume(self.volume.master_output());
+        self.audio.set_limiter(self.volume.limiter);
+        self.audio.set_speed(self.speed);
+        self.volume.apply_channels(&gameboy.audio_control);
+
+        saves.set_rom_info(Some(gameboy.rom_info.clone()));
+
+        self.audio.set_audio_control(gameboy.audio_control.clone());
+        self.audio.play();
+
+        match gameboy.start() {
+            Ok(_) => log::info!("Emulation started"),
+            Err(error) => {
+                self.notifications
+                    .error(format!("Unable to start the game: {error}"));
             }
-        });
-        ui.horizontal(|ui| {
-            ui.monospace("Select:   ".to_string());
-            if ui
-                .text_edit_singleline(&mut inputs.select.to_string())
-                .has_focus()
-            {
-                inputs.update_buttons(crate::input::GBButton::Select);
-                self.input_state = inputs.save();
+        };
+
+        self.cgb_mode = match self.bootrom_options.gb_type {
+            Some(gb_type) => gb_type == GameboyType::CGB,
+            None => !matches!(rom_type, CartType::DMG),
+        };
+        self.gameboy.replace(gameboy);
+        //RomInfo has already read the header, but not its checksum
+        self.rom_warning = (crate::rom::header_checksum_ok(&rom) == Some(false))
+            .then(|| "ROM may be corrupt, the header checksum doesn't match".to_string());
+        if let Some(warning) = &self.rom_warning {
+            log::warn!("{name}: {warning}");
+        }
+        self.rom = Some(rom);
+
+        self.menu_visible = false;
+    }
+
+    fn remember_rom(&mut self, _path: Option<PathBuf>) {
+        let Some(gameboy) = &self.gameboy else {
+            return;
+        };
+        let name = gameboy.rom_info.get_name();
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let Some(location) = _path
+        else {
+            return;
+        };
+        #[cfg(target_arch = "wasm32")]
+        let location = {
+            let key = format!("{RECENT_ROM_PREFIX}{name}");
+            if let (Some(saves), Some(rom)) = (&mut self.saves, &self.rom) {
+                saves.save(&key, rom);
             }
-        });
-        ui.horizontal(|ui| {
-            ui.monospace("Start:    ".to_string());
-            if ui
-                .text_edit_singleline(&mut inputs.start.to_string())
-                .has_focus()
-            {
-                inputs.update_buttons(crate::input::GBButton::Start);
-                self.input_state = inputs.save();
+            PathBuf::from(key)
+        };
+
+        self.recent_roms
+            .retain(|recent| recent.location != location);
+        self.recent_roms.push_front(RecentRom { name, location });
+        while self.recent_roms.len() > MAX_RECENT_ROMS {
+            if let Some(_removed) = self.recent_roms.pop_back() {
+                #[cfg(target_arch = "wasm32")]
+                if let Some(saves) = &mut self.saves {
+                    saves.delete(&_removed.location.to_string_lossy());
+                }
             }
-        });
-        ui.horizontal(|ui| {
-            ui.monospace("Up:       ".to_string());
-            if ui
-                .text_edit_singleline(&mut inputs.up.to_string())
-                .has_focus()
-            {
-                inputs.update_buttons(crate::input::GBButton::Up);
-                self.input_state = inputs.save();
+        }
+    }
+
+    fn open_recent(&mut self, index: usize) {
+        let Some(recent) = self.recent_roms.get(index) else {
+            return;
+        };
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let rom = std::fs::read(&recent.location).ok();
+        #[cfg(target_arch = "wasm32")]
+        let rom = self
+            .saves
+            .as_mut()
+            .and_then(|saves| saves.load(&recent.location.to_string_lossy()));
+
+        match rom {
+            Some(rom) => {
+                let location = recent.location.clone();
+                self.events.push(Event::OpenRom(rom, Some(location)));
             }
-        });
+            None => {
+                self.notifications.error(format!(
+                    "Unable to open {}, removing it from recent roms",
+                    recent.name
+                ));
+                self.recent_roms.remove(index);
+            }
+        }
+    }
+
+    fn display_recent_roms(&mut self, ui: &mut egui::Ui) {
+        let mut opened = None;
+        for (index, recent) in self.recent_roms.iter().enumerate() {
+            let button = ui
+                .add_sized(
+                    [ui.available_width(), 0.0],
+                    egui::Button::new(RichText::new(&recent.name).small()),
+                )
+                .on_hover_text(recent.location.to_string_lossy());
+            if button.clicked() {
+                opened = Some(index);
+            }
+        }
+        if let Some(index) = opened {
+            self.open_recent(index);
+        }
+    }
+
+    //Starts the current game over from the rom we already have, like flipping the power switch
+    fn reset(&mut self) {
+        //A copy, the running game keeps going with this one if the new build fails
+        if let Some(rom) = self.rom.clone() {
+            self.emulator_error = None;
+            self.load_rom(rom, true);
+        }
+    }
+
+    //Closes the game and goes back to the menu
+    fn eject(&mut self) {
+        self.unload_rom();
+        self.menu_visible = true;
+    }
+
+    //Restarts the game for a setting that only applies when it's loaded, leaving the menu open
+    fn reload(&mut self) {
+        if self.gameboy.is_none() {
+            return;
+        }
+        let menu_visible = self.menu_visible;
+        self.reset();
+        self.menu_visible = menu_visible;
+    }
+
+    //Tears down the running game so nothing from it (audio stream, last frame) outlives it
+    fn unload_rom(&mut self) {
+        //The audio stream gets replaced with the next game, so finish the recording now
+        self.stop_recording();
+        if let Some(gameboy) = self.gameboy.take() {
+            if let Some(saves) = &mut self.saves {
+                saves.flush_current(&gameboy.rom_info.get_name());
+            }
+        }
+        self.audio.pause();
+        self.audio.clear_audio_control();
+        self.palettes.rom_unloaded();
+        self.gb_texture = None;
+        self.last_frame = None;
+        self.stats.clear();
+        self.rom = None;
+        self.rom_warning = None;
+        self.global_checksum = OnceCell::new();
+        self.paused = false;
+        self.focus_paused = false;
+        self.advancing = false;
+    }
+
+    //solgb has no pause control of its own, it's paced by the audio stream pulling samples from it, so
+    //stopping the stream is what halts it. The last frame is left in the texture.
+    pub fn pause(&mut self) {
+        if self.gameboy.is_some() {
+            self.paused = true;
+            self.audio.pause();
+        }
+    }
+
+    pub fn resume(&mut self) {
+        self.focus_paused = false;
+        self.advancing = false;
+        if !self.paused {
+            return;
+        }
+        self.paused = false;
+        if let Some(gameboy) = &self.gameboy {
+            //Don't show anything that was queued up before pausing
+            while gameboy.video_rec.try_recv().is_ok() {}
+        }
+        self.audio.play();
+    }
+
+    //Goes off the focused state instead of WindowFocused events, the browser doesn't always send those
+    fn handle_focus(&mut self, ctx: &egui::Context) {
+        if !self.settings.pause_on_focus_loss {
+            if self.focus_paused {
+                self.focus_paused = false;
+                self.resume();
+            }
+            return;
+        }
+
+        let focused = |i: &egui::InputState| i.viewport().focused.unwrap_or(true);
+        let focused = ctx.input(focused)
+            || (self.display.separate_window
+                && ctx.input_for(DisplayOptions::game_viewport(), focused));
+        if !focused && !self.paused && self.gameboy.is_some() {
+            log::info!("Window lost focus, pausing");
+            self.pause();
+            self.focus_paused = true;
+        } else if focused && self.focus_paused {
+            log::info!("Window regained focus, resuming");
+            self.focus_paused = false;
+            self.resume();
+        }
+    }
+
+    //Pauses if needed, then lets the emulator run until update gets one more frame from it
+    fn advance_frame(&mut self) {
+        if self.gameboy.is_none() {
+            return;
+        }
+        self.pause();
+        self.advancing = true;
+        self.audio.play();
+    }
+
+    fn toggle_pause(&mut self) {
+        if self.paused {
+            self.resume();
+        } else {
+            self.pause();
+        }
+    }
+
+    fn game_buttons(&mut self, ui: &mut egui::Ui) {
+        if self.gameboy.is_none() {
+            return;
+        }
+        let icon = if self.paused { "▶" } else { "⏸" };
+        if ui.button(RichText::new(icon).monospace()).clicked() {
+            self.toggle_pause();
+        }
+        if ui
+            .button(RichText::new("⟲").monospace())
+            .on_hover_text("Reset")
+            .clicked()
+        {
+            self.reset();
+        }
+        if ui
+            .button(RichText::new("📷").monospace())
+            .on_hover_text("Screenshot")
+            .clicked()
+        {
+            self.screenshot();
+        }
+        if ui
+            .button(RichText::new("⏏").monospace())
+            .on_hover_text("Eject")
+            .clicked()
+        {
+            self.eject();
+        }
+    }
+
+    //Saves the last frame we got as a png at the gameboy's own resolution
+    pub fn screenshot(&self) {
+        let Some(frame) = &self.last_frame else {
+            log::warn!("No frame to take a screenshot of");
+            return;
+        };
+
+        //Frames are BGRA, the png doesn't need the alpha channel
+        let bytes: &[u8] = bytemuck::cast_slice(frame);
+        let pixels = bytes
+            .chunks_exact(4)
+            .flat_map(|p| [p[2], p[1], p[0]])
+            .collect();
+        let Some(image) = image::RgbImage::from_raw(WIDTH as u32, HEIGHT as u32, pixels) else {
+            log::error!("Frame is not {WIDTH}x{HEIGHT}");
+            return;
+        };
+
+        let mut png = std::io::Cursor::new(Vec::new());
+        if let Err(err) = image.write_to(&mut png, image::ImageFormat::Png) {
+            self.notifications
+                .error(format!("Unable to encode screenshot: {err}"));
+            return;
+        }
+
+        let name = self
+            .gameboy
+            .as_ref()
+            .map(|gameboy| gameboy.rom_info.get_name())
+            .unwrap_or("screenshot".into());
+        save_file(
+            &format!("{name}.png"),
+            ("PNG Image", &["png"]),
+            &png.into_inner(),
+        );
+    }
+
+    //Names the running game in the window title, only sending the command when it changes
+    fn update_title(&mut self, ctx: &egui::Context) {
+        let title = match &self.gameboy {
+            Some(gameboy) => format!("{WINDOW_TITLE} - {}", gameboy.rom_info.get_name()),
+            None => WINDOW_TITLE.to_string(),
+        };
+        if title != self.window_title {
+            ctx.send_viewport_cmd(egui::ViewportCommand::Title(title.clone()));
+            self.window_title = title;
+        }
+    }
+
+    //The emulation thread has gone away, keep the last frame on screen and let the user decide what to do
+    fn emulator_stopped(&mut self) {
+        let name = self
+            .gameboy
+            .take()
+            .map(|gameboy| gameboy.rom_info.get_name());
+        //Whatever the game last wrote is still in save_ram, unload_rom won't see it with the gameboy gone
+        if let (Some(saves), Some(name)) = (&mut self.saves, &name) {
+            saves.flush_current(name);
+        }
+        let message = format!("The emulator stopped running {}", name.unwrap_or_default());
+        log::error!("{message}");
+        self.audio.pause();
+        self.emulator_error = Some(message);
+    }
+
+    fn display_emulator_error(&mut self, ctx: &egui::Context) {
+        let Some(message) = &self.emulator_error else {
+            return;
+        };
+
+        let mut return_to_menu = false;
+        let mut reset = false;
+        egui::Window::new("Emulator stopped")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(message);
+                ui.horizontal(|ui| {
+                    reset = self.rom.is_some() && ui.button("Reset").clicked();
+                    return_to_menu = ui.button("Return to menu").clicked();
+                });
+            });
+
+        if reset {
+            self.reset();
+        }
+
+        if return_to_menu {
+            self.emulator_error = None;
+            self.unload_rom();
+            self.menu_visible = true;
+        }
+    }
+
+    fn display_pending_zip(&mut self, ctx: &egui::Context) {
+        let Some(pending) = &self.pending_zip else {
+            return;
+        };
+
+        let mut chosen = None;
+        let mut cancel = false;
+        egui::Window::new("Choose a ROM")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label("This zip file has more than one ROM in it");
+                for (index, (name, _rom)) in pending.roms.iter().enumerate() {
+                    if ui.button(name).clicked() {
+                        chosen = Some(index);
+                    }
+                }
+                cancel = ui.button("Cancel").clicked();
+            });
+
+        if let Some(index) = chosen {
+            if let Some(mut pending) = self.pending_zip.take() {
+                let (_name, rom) = pending.roms.swap_remove(index);
+                self.events.push(Event::OpenRom(rom, pending.path));
+            }
+        } else if cancel {
+            self.pending_zip = None;
+        }
+    }
+
+    fn display_pending_save(&mut self, ctx: &egui::Context) {
+        let Some(pending) = &mut self.pending_save else {
+            return;
+        };
+
+        let mut done = false;
+        egui::Window::new("Save size mismatch")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "{} is {} bytes but the running game expects {} bytes. \
+                     It may be for a different game or be incomplete.",
+                    pending.name,
+                    pending.data.len(),
+                    pending.expected
+                ));
+                ui.horizontal(|ui| {
+                    let import = ui.button("Import anyway").clicked();
+                    let resize = ui
+                        .button(format!("Resize to {} bytes", pending.expected))
+                        .on_hover_text("Pad with zeros or cut off the end")
+                        .clicked();
+                    if resize {
+                        pending.data.resize(pending.expected, 0);
+                    }
+                    if import || resize {
+                        if let Some(saves) = &mut self.saves {
+                            saves.save(&pending.name, &pending.data);
+                        }
+                        done = true;
+                    }
+                    done |= ui.button("Cancel").clicked();
+                });
+            });
+
+        if done {
+            self.pending_save = None;
+        }
+    }
+
+    fn track_play_time(&mut self) {
+        //Big gaps mean we weren't being updated (hidden tab, minimized), so don't count them
+        const MAX_STEP: Duration = Duration::from_secs(1);
+        let elapsed = self.last_update.elapsed().min(MAX_STEP);
+        self.last_update = Instant::now();
+
+        if let Some(gameboy) = self.gameboy.as_ref().filter(|_| !self.paused) {
+            *self
+                .play_time
+                .entry(gameboy.rom_info.get_name())
+                .or_default() += elapsed.as_secs_f64();
+        }
+    }
+
+    fn apply_palette(&mut self) {
+        if let Some(gameboy) = &mut self.gameboy {
+            let pal = self.palettes.get_u32_palette();
+            gameboy.set_palettes(PaletteColors::new((pal[0], pal[1], pal[2])))
+        }
+    }
+
+    fn swap_palette(&mut self) {
+        let name = self.palettes.swap_palette();
+        self.apply_palette();
+        self.palette_indicator = Some((name, Instant::now()));
+    }
+
+    fn handle_hotkeys(&mut self, ctx: &egui::Context) {
+        let inputs = get_inputs(&mut self.inputs, ctx, &self.input_state);
+        let chord = inputs.pressed(GBButton::Select) && inputs.pressed(GBButton::Start);
+        if self.hotkeys.menu_chord && chord && !self.menu_chord_held {
+            self.menu_visible = !self.menu_visible;
+        }
+        self.menu_chord_held = chord;
+
+        //Don't steal keys from text fields or while a hotkey or button is being rebound
+        if ctx.wants_keyboard_input()
+            || self.hotkey_rebinding.is_some()
+            || self.button_rebinding.is_some()
+        {
+            return;
+        }
+
+        if self.hotkeys.pressed(Hotkey::ToggleMenu, ctx) {
+            self.menu_visible = !self.menu_visible;
+        }
+
+        if self.hotkeys.pressed(Hotkey::SwapPalette, ctx) {
+            self.swap_palette();
+        }
+
+        if self.hotkeys.pressed(Hotkey::Mute, ctx) {
+            self.toggle_mute();
+        }
+
+        if self.hotkeys.pressed(Hotkey::Pause, ctx) {
+            self.toggle_pause();
+        }
+
+        if self.hotkeys.pressed(Hotkey::Screenshot, ctx) {
+            self.screenshot();
+        }
+
+        if self.hotkeys.pressed(Hotkey::FrameAdvance, ctx) {
+            self.advance_frame();
+        }
+    }
+
+    #[cfg(all(feature = "ipc", not(target_arch = "wasm32")))]
+    fn handle_ipc(&mut self, ctx: &egui::Context) {
+        use crate::ipc::Command;
+
+        let Some(ipc) = &self.ipc else {
+            return;
+        };
+
+        for command in ipc.commands() {
+            match command {
+                Command::Load(path) => self.open_path(path),
+                Command::ToggleMenu => self.menu_visible = !self.menu_visible,
+                Command::SwapPalette => self.swap_palette(),
+                Command::ToggleMute => self.toggle_mute(),
+                Command::Pause => self.pause(),
+                Command::Resume => self.resume(),
+                Command::Reset => self.reset(),
+                Command::Screenshot => self.screenshot(),
+                Command::Quit => ctx.send_viewport_cmd(egui::ViewportCommand::Close),
+            }
+        }
+    }
+
+    fn display_indicators(&mut self, ctx: &egui::Context) {
+        if let Some(warning) = &self.rom_warning {
+            let mut dismissed = false;
+            egui::Area::new(egui::Id::new("rom_warning"))
+                .anchor(egui::Align2::CENTER_TOP, [0.0, 8.0])
+                .show(ctx, |ui| {
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            ui.colored_label(ui.visuals().warn_fg_color, format!("⚠ {warning}"));
+                            dismissed = ui.small_button("✖").clicked();
+                        });
+                    });
+                });
+            if dismissed {
+                self.rom_warning = None;
+            }
+        }
+
+        if let Some((name, shown)) = &self.palette_indicator {
+            if shown.elapsed() > INDICATOR_DURATION {
+                self.palette_indicator = None;
+            } else {
+                egui::Area::new(egui::Id::new("palette_indicator"))
+                    .anchor(egui::Align2::RIGHT_TOP, [-8.0, 8.0])
+                    .interactable(false)
+                    .show(ctx, |ui| {
+                        ui.label(RichText::new(format!("🎨 {name}")).monospace());
+                    });
+            }
+        }
+
+        //Let the user know stutter is from the emulator falling behind
+        if let Some(caught_up) = &self.last_catch_up {
+            if caught_up.elapsed() > INDICATOR_DURATION {
+                self.last_catch_up = None;
+            } else {
+                egui::Area::new(egui::Id::new("behind_indicator"))
+                    .anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -8.0])
+                    .show(ctx, |ui| {
+                        ui.label(RichText::new("🐢").monospace())
+                            .on_hover_text(format!(
+                                "Running behind, {} frames skipped so far",
+                                self.dropped_frames
+                            ));
+                    });
+            }
+        }
+    }
+
+    fn display_inputs(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        const BUTTONS: [(GBButton, &str); 8] = [
+            (GBButton::A, "A:"),
+            (GBButton::B, "B:"),
+            (GBButton::Select, "Select:"),
+            (GBButton::Start, "Start:"),
+            (GBButton::Up, "Up:"),
+            (GBButton::Down, "Down:"),
+            (GBButton::Left, "Left:"),
+            (GBButton::Right, "Right:"),
+        ];
         ui.horizontal(|ui| {
-            ui.monospace("Down:     ".to_string());
-            if ui
-                .text_edit_singleline(&mut inputs.down.to_string())
-                .has_focus()
-            {
-                inputs.update_buttons(crate::input::GBButton::Down);
-                self.input_state = inputs.save();
+            for (player, label) in [(Player::One, "Player 1"), (Player::Two, "Player 2")] {
+                if ui
+                    .selectable_value(&mut self.bindings_player, player, label)
+                    .changed()
+                {
+                    self.button_rebinding = None;
+                }
             }
         });
+        let (inputs, input_state) = match self.bindings_player {
+            Player::One => (
+                get_inputs(&mut self.inputs, ctx, &self.input_state),
+                &mut self.input_state,
+            ),
+            Player::Two => (
+                get_inputs(&mut self.inputs_p2, ctx, &self.input_state_p2),
+                &mut self.input_state_p2,
+            ),
+        };
+        //Capture the next key or gamepad button pressed, escape gives up
+        if let Some(gb_button) = self.button_rebinding {
+            if ctx.input(|i| i.key_pressed(egui::Key::Escape)) {
+                self.button_rebinding = None;
+            } else if inputs.update_buttons(gb_button) {
+                self.button_rebinding = None;
+                *input_state = inputs.save();
+            }
+        }
+        for (gb_button, label) in BUTTONS {
+            ui.horizontal_wrapped(|ui| {
+                ui.monospace(format!("{label: <10}"));
+                let labels = inputs.binding_labels(gb_button);
+                let mut removed = None;
+                for (index, binding) in labels.iter().enumerate() {
+                    if ui
+                        .button(format!("{binding} ✖"))
+                        .on_hover_text("Remove")
+                        .clicked()
+                    {
+                        removed = Some(index);
+                    }
+                }
+                if let Some(index) = removed {
+                    if let Some(bindings) = inputs.bindings(gb_button) {
+                        bindings.remove(index);
+                    }
+                    *input_state = inputs.save();
+                }
+
+                if labels.is_empty() {
+                    ui.weak("unbound");
+                } else if ui.button("Clear").clicked() {
+                    if let Some(bindings) = inputs.bindings(gb_button) {
+                        bindings.clear();
+                    }
+                    *input_state = inputs.save();
+                }
+
+                let text = if self.button_rebinding == Some(gb_button) {
+                    "press a button... (esc cancels)"
+                } else {
+                    "+ add"
+                };
+                if ui.button(text).clicked() {
+                    self.button_rebinding = match self.button_rebinding {
+                        Some(rebinding) if rebinding == gb_button => None,
+                        _ => Some(gb_button),
+                    };
+                    //Only presses from here on should count
+                    if self.button_rebinding.is_some() {
+                        inputs.discard_events();
+                    }
+                }
+            });
+        }
+
+        //Hotkeys only ever conflict with the first player, they're the only one being played
+        let inputs = get_inputs(&mut self.inputs, ctx, &self.input_state);
+        if inputs.gilrs.is_none() {
+            ui.label("Gamepad support is unavailable, running keyboard only");
+        }
+
         ui.horizontal(|ui| {
-            ui.monospace("Left:     ".to_string());
-            if ui
-                .text_edit_singleline(&mut inputs.left.to_string())
-                .has_focus()
-            {
-                inputs.update_buttons(crate::input::GBButton::Left);
-                self.input_state = inputs.save();
-            }
+            ui.monospace("Autofire  ");
+            ui.checkbox(&mut self.autofire.a, "A");
+            ui.checkbox(&mut self.autofire.b, "B");
+            ui.add(egui::Slider::new(&mut self.autofire.rate, Autofire::RATES).suffix(" Hz"));
         });
-        ui.horizontal(|ui| {
-            ui.monospace("Right:    ".to_string());
-            if ui
-                .text_edit_singleline(&mut inputs.right.to_string())
-                .has_focus()
-            {
-                inputs.update_buttons(crate::input::GBButton::Right);
-                self.input_state = inputs.save();
+
+        ui.monospace("Hotkeys");
+        if let Some(hotkey) = self.hotkey_rebinding {
+            let shortcut = ctx.input(|i| {
+                i.events.iter().find_map(|event| match event {
+                    egui::Event::Key {
+                        key,
+                        pressed: true,
+                        modifiers,
+                        ..
+                    } => Some(KeyboardShortcut::new(*modifiers, *key)),
+                    _ => None,
+                })
+            });
+            if let Some(shortcut) = shortcut {
+                self.hotkeys.set(hotkey, shortcut);
+                self.hotkey_rebinding = None;
             }
-        });
+        }
+        for hotkey in Hotkey::ALL {
+            ui.horizontal(|ui| {
+                ui.monospace(format!("{: <10}", format!("{}:", hotkey.label())));
+                let shortcut = *self.hotkeys.get(hotkey);
+                let text = if self.hotkey_rebinding == Some(hotkey) {
+                    "press a key...".to_string()
+                } else {
+                    ctx.format_shortcut(&shortcut)
+                };
+                if ui.button(text).clicked() {
+                    self.hotkey_rebinding = match self.hotkey_rebinding {
+                        Some(rebinding) if rebinding == hotkey => None,
+                        _ => Some(hotkey),
+                    };
+                }
+                if inputs.is_bound(shortcut.logical_key) {
+                    ui.label("⚠ also bound to a gameboy button");
+                }
+            });
+        }
+        ui.checkbox(
+            &mut self.hotkeys.menu_chord,
+            "Select + Start opens the menu",
+        )
+        .on_hover_text("The game still sees the buttons, some games reset on this combination");
 
         ui.checkbox(&mut self.touch_visible, "Show Touch Controls (WIP)");
+        if self.touch_visible {
+            self.touch.display_options(ui);
+        }
     }
 
     pub fn display_boot_roms(&mut self, ui: &mut egui::Ui) {
         ui.checkbox(&mut self.bootrom_options.use_bootrom, "Use Bootrom");
 
+        let model = self.bootrom_options.gb_type;
         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
             ui.radio_value(&mut self.bootrom_options.gb_type, None, "Auto");
             ui.radio_value(
@@ -303,77 +1195,290 @@ impl TemplateApp {
                 "CGB",
             );
         });
+        if self.bootrom_options.gb_type != model {
+            self.reload();
+        }
 
         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
             if ui.button("upload DMG").clicked() {
                 open(
                     &self.events,
-                    &[("Gameboy bootroom", &["bin", "rom"]), ("All Files", &["*"])],
+                    &[("Gameboy bootroom", &["bin", "rom"]), ALL_FILES],
                     EventType::BootromUpload(GameboyType::DMG),
+                    &mut self.last_dirs,
                 );
             }
 
             if ui.button("upload CGB").clicked() {
                 open(
                     &self.events,
-                    &[
-                        ("Gameboy Color bootroom", &["bin", "rom"]),
-                        ("All Files", &["*"]),
-                    ],
+                    &[("Gameboy Color bootroom", &["bin", "rom"]), ALL_FILES],
                     EventType::BootromUpload(GameboyType::CGB),
+                    &mut self.last_dirs,
                 );
             }
         });
     }
 
+    fn display_screen(&self, ui: &mut egui::Ui) {
+        let Some(gb_texture) = &self.gb_texture else {
+            return;
+        };
+        ui.vertical_centered(|ui| {
+            let gameboy =
+                egui::Image::new(ImageSource::Texture(SizedTexture::from_handle(gb_texture)));
+            let gameboy =
+                self.display
+                    .scale
+                    .size(gameboy, ui.available_size(), ui.ctx().pixels_per_point());
+            let shown = ui.add(gameboy);
+            self.display
+                .paint_grid(&ui.painter_at(shown.rect), shown.rect);
+        });
+    }
+
+    fn display_rom_info(&self, ui: &mut egui::Ui) {
+        let (Some(gameboy), Some(rom)) = (&self.gameboy, &self.rom) else {
+            return;
+        };
+        let rom_info = &gameboy.rom_info;
+        let size = |bytes: Option<usize>| match bytes {
+            Some(0) => "None".to_string(),
+            Some(bytes) if bytes < 1024 => format!("{bytes} B"),
+            Some(bytes) => format!("{} KiB", bytes / 1024),
+            None => "Unknown".to_string(),
+        };
+        let yes_no = |flag: bool| if flag { "Yes" } else { "No" };
+
+        egui::Grid::new("rom_info").num_columns(2).show(ui, |ui| {
+            ui.label("Title");
+            ui.monospace(rom_info.get_name());
+            ui.end_row();
+            ui.label("Cart type");
+            ui.label(match rom_info.get_type() {
+                CartType::DMG => "DMG",
+                _ => "CGB",
+            });
+            ui.end_row();
+            ui.label("Running as");
+            ui.label(if self.cgb_mode { "CGB" } else { "DMG" });
+            ui.end_row();
+            ui.label("Mapper");
+            ui.label(crate::rom::mapper(rom).unwrap_or("Unknown"));
+            ui.end_row();
+            ui.label("ROM size");
+            ui.label(size(crate::rom::rom_size(rom)));
+            ui.end_row();
+            ui.label("RAM size");
+            ui.label(size(crate::rom::ram_size(rom)));
+            ui.end_row();
+            ui.label("Battery");
+            ui.label(yes_no(rom_info.is_battery_backed()));
+            ui.end_row();
+            ui.label("Real time clock");
+            ui.label(yes_no(crate::rom::has_rtc(rom)));
+            ui.end_row();
+            ui.label("Color support");
+            ui.label(crate::rom::cgb_support(rom));
+            ui.end_row();
+            ui.label("Global checksum");
+            let global = self
+                .global_checksum
+                .get_or_init(|| crate::rom::global_checksum_ok(rom));
+            ui.label(match global {
+                Some(true) => "OK",
+                Some(false) => "Doesn't match",
+                None => "Unknown",
+            });
+            ui.end_row();
+            ui.label("Play time");
+            let played = self
+                .play_time
+                .get(&rom_info.get_name())
+                .copied()
+                .unwrap_or_default();
+            ui.label(format_play_time(played));
+            ui.end_row();
+        });
+    }
+
+    fn display_settings(&mut self, ui: &mut egui::Ui) {
+        ui.checkbox(
+            &mut self.settings.all_files_default,
+            "Show all files when opening a ROM",
+        );
+        ui.checkbox(&mut self.stats.visible, "Show frame rate overlay");
+        ui.checkbox(&mut self.stats.graph_visible, "Show frame time graph");
+        ui.checkbox(&mut self.settings.verbose_logging, "Verbose logging");
+        ui.checkbox(
+            &mut self.settings.pause_on_focus_loss,
+            "Pause on focus loss",
+        );
+        ui.checkbox(&mut self.settings.latch_inputs, "Latch quick button taps")
+            .on_hover_text(
+                "Presses shorter than a frame are held until the game has seen them. \
+                 Turn off to sample buttons exactly once per repaint.",
+            );
+        ui.add(
+            egui::Slider::new(
+                &mut self.settings.max_frame_backlog,
+                Settings::BACKLOG_RANGE,
+            )
+            .text("Max frame backlog"),
+        )
+        .on_hover_text(
+            "Frames allowed to queue up before skipping to the newest one. \
+             1 has the lowest latency but drops frames whenever rendering falls behind, \
+             higher values are smoother on slow machines.",
+        );
+    }
+
+    fn toggle_recording(&mut self) {
+        if self.audio.recording_time().is_none() {
+            self.audio.start_recording();
+        } else {
+            self.stop_recording();
+        }
+    }
+
+    fn stop_recording(&mut self) {
+        if let Some(wav) = self.audio.stop_recording() {
+            let name = self
+                .gameboy
+                .as_ref()
+                .map(|gameboy| gameboy.rom_info.get_name())
+                .unwrap_or("recording".into());
+            save_file(&format!("{name}.wav"), ("WAV Audio", &["wav"]), &wav);
+        }
+    }
+
+    fn toggle_mute(&mut self) {
+        self.volume.muted = !self.volume.muted;
+        self.audio.set_volume(self.volume.master_output());
+    }
+
+    fn display_speed(&mut self, ctx: &egui::Context, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.monospace("Speed: ");
+            for (speed, label) in SPEEDS {
+                if ui.radio_value(&mut self.speed, speed, label).changed() {
+                    self.audio.set_speed(self.speed);
+                }
+            }
+        });
+        let shortcut = ctx.format_shortcut(self.hotkeys.get(Hotkey::FrameAdvance));
+        if ui
+            .add_enabled(self.gameboy.is_some(), egui::Button::new("Frame advance"))
+            .on_hover_text(format!("Pauses and runs the next frame ({shortcut})"))
+            .clicked()
+        {
+            self.advance_frame();
+        }
+    }
+
     fn display_volume(&mut self, ui: &mut egui::Ui) {
         const VOLUME_RANGE: RangeInclusive<u32> = 0..=100;
+        const VOLUME_PRESETS: [u32; 3] = [25, 50, 100];
+        if self.audio.is_disabled() {
+            ui.label("No audio device");
+            return;
+        }
+        let record = match self.audio.recording_time() {
+            Some(time) => format!(
+                "⏹ Stop recording ({})",
+                format_play_time(time.as_secs_f64())
+            ),
+            None => "⏺ Record".to_string(),
+        };
+        if ui.button(record).clicked() {
+            self.toggle_recording();
+        }
         if ui
             .add(egui::Slider::new(&mut self.volume.master, VOLUME_RANGE).text("Master"))
             .changed()
         {
-            self.audio.set_volume(self.volume.master as u8);
+            self.volume.muted = false;
+            self.audio.set_volume(self.volume.master_output());
         };
-        if ui
-            .add(egui::Slider::new(&mut self.volume.square_1, VOLUME_RANGE).text("Square 1"))
-            .changed()
-        {
-            if let Some(gameboy) = &self.gameboy {
-                gameboy
-                    .audio_control
-                    .set_volume(Channel::Square1, self.volume.square_1 as f32)
+        ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+            if ui.checkbox(&mut self.volume.muted, "Mute").changed() {
+                self.audio.set_volume(self.volume.master_output());
             }
-        };
-        if ui
-            .add(egui::Slider::new(&mut self.volume.square_2, VOLUME_RANGE).text("Square 2"))
-            .changed()
-        {
-            if let Some(gameboy) = &self.gameboy {
-                gameboy
-                    .audio_control
-                    .set_volume(Channel::Square2, self.volume.square_2 as f32)
+            for preset in VOLUME_PRESETS {
+                if ui.button(format!("{preset}%")).clicked() {
+                    self.volume.master = preset;
+                    self.volume.muted = false;
+                    self.audio.set_volume(self.volume.master_output());
+                }
             }
-        };
-        if ui
-            .add(egui::Slider::new(&mut self.volume.wave, VOLUME_RANGE).text("Wave"))
-            .changed()
-        {
-            if let Some(gameboy) = &self.gameboy {
-                gameboy
-                    .audio_control
-                    .set_volume(Channel::Wave, self.volume.wave as f32)
+        });
+        let mut device = self.volume.output_device.clone();
+        egui::ComboBox::from_label("Output")
+            .selected_text(device.as_deref().unwrap_or("Default"))
+            .show_ui(ui, |ui| {
+                ui.selectable_value(&mut device, None, "Default");
+                for name in self.audio.device_names() {
+                    ui.selectable_value(&mut device, Some(name.clone()), name);
+                }
+            });
+        if device != self.volume.output_device {
+            self.volume.output_device = device;
+            self.audio.set_device(self.volume.output_device.as_deref());
+            if self.gameboy.is_some() && !self.paused {
+                self.audio.play();
             }
-        };
+        }
+        ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+            ui.monospace("Latency: ");
+            for latency in Latency::ALL {
+                if ui
+                    .radio_value(&mut self.volume.latency, latency, latency.label())
+                    .changed()
+                {
+                    self.audio.set_latency(latency);
+                    if self.gameboy.is_some() && !self.paused {
+                        self.audio.play();
+                    }
+                }
+            }
+        });
         if ui
-            .add(egui::Slider::new(&mut self.volume.noise, VOLUME_RANGE).text("Noise"))
+            .checkbox(&mut self.volume.limiter, "Limiter")
+            .on_hover_text("Keep loud channels from clipping when they're stacked")
             .changed()
         {
+            self.audio.set_limiter(self.volume.limiter);
+        }
+        let mut changed = false;
+        changed |= channel_volume(
+            ui,
+            "Square 1",
+            &mut self.volume.square_1,
+            &mut self.volume.square_1_enabled,
+        );
+        changed |= channel_volume(
+            ui,
+            "Square 2",
+            &mut self.volume.square_2,
+            &mut self.volume.square_2_enabled,
+        );
+        changed |= channel_volume(
+            ui,
+            "Wave",
+            &mut self.volume.wave,
+            &mut self.volume.wave_enabled,
+        );
+        changed |= channel_volume(
+            ui,
+            "Noise",
+            &mut self.volume.noise,
+            &mut self.volume.noise_enabled,
+        );
+        if changed {
             if let Some(gameboy) = &self.gameboy {
-                gameboy
-                    .audio_control
-                    .set_volume(Channel::Noise, self.volume.noise as f32)
+                self.volume.apply_channels(&gameboy.audio_control);
             }
-        };
+        }
     }
 }
 
@@ -385,89 +1490,174 @@ impl eframe::App for TemplateApp {
 
     /// Called each time the UI needs repainting, which may be many times per second.
     fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
-        // //This does not work as expected in the browser
-        // ctx.input(|i| {
-        //     if let Some(stream) = &self.stream {
-        //         for event in &i.raw.events {
-        //             match event {
-        //                 egui::Event::WindowFocused(focused) => {
-        //                     if !focused {
-        //                         log::error!("Window has lost focus, pausing");
-        //                         stream.pause().unwrap();
-        //                     } else {
-        //                         log::error!("Window has gained focus, resuming");
-        //                         stream.play().unwrap();
-        //                     }
-        //                 }
-        //                 _ => (),
-        //             }
-        //         }
-        //     }
-        // });
-
         egui_extras::install_image_loaders(ctx);
+        self.stats.updated();
 
         self.handle_custom_events();
 
+        self.update_title(ctx);
+
+        self.handle_focus(ctx);
+
+        //Rebinding ignores hotkeys, so it can't be left going once there's no way to finish it
+        if !self.menu_visible || !self.inputs_visible {
+            self.button_rebinding = None;
+            self.hotkey_rebinding = None;
+        }
+
+        //Gamepad state only updates as its events are drained, so this happens even while paused
+        //or on the menu. Rebinding wants the events for itself
+        let rebinding_p1 = self.button_rebinding.is_some() && self.bindings_player == Player::One;
+        if !rebinding_p1 {
+            let running = self.gameboy.is_some() && (!self.paused || self.advancing);
+            get_inputs(&mut self.inputs, ctx, &self.input_state)
+                .poll(self.settings.latch_inputs && running);
+        }
+
+        self.handle_hotkeys(ctx);
+
+        self.track_play_time();
+
+        #[cfg(all(feature = "ipc", not(target_arch = "wasm32")))]
+        self.handle_ipc(ctx);
+
         if let Some(saves) = &mut self.saves {
             if let Some(gameboy) = &self.gameboy {
                 saves.save_current(&gameboy.rom_info.get_name());
             }
         }
 
-        if let Some(gameboy) = &mut self.gameboy {
-            if gameboy.video_rec.len() > 60 {
+        self.audio.discard_samples();
+
+        let mut stopped = false;
+        let mut new_frame = false;
+        let paused = self.paused && !self.advancing;
+        if let Some(gameboy) = self.gameboy.as_mut().filter(|_| !paused) {
+            self.stats.repainted(gameboy.video_rec.len());
+            let max_backlog = self.settings.max_frame_backlog;
+            if gameboy.video_rec.len() > max_backlog {
                 log::warn!(
-                    "We are over 1 second behind on rendering frames.\nskipping to current frame"
+                    "We are over {max_backlog} frames behind on rendering frames.\nskipping to current frame"
                 );
-                while gameboy.video_rec.try_recv().is_ok() {}
-            }
-            log::info!("Rendering Frame for: {}", gameboy.rom_info.get_name());
-            if let Ok(buffer_u32) = gameboy.video_rec.try_recv() {
-                if let Ok(buffer) = bytemuck::try_cast_slice(&buffer_u32) {
-                    let image = Arc::new(ColorImage {
-                        size: [WIDTH, HEIGHT],
-                        pixels: {
-                            assert_eq!(WIDTH * HEIGHT * 4, buffer.len());
-                            buffer
-                                .chunks_exact(4)
-                                .map(|p| Color32::from_rgba_premultiplied(p[2], p[1], p[0], p[3]))
-                                .collect()
-                        },
-                    });
-                    match &mut self.gb_texture {
-                        Some(texture) => texture.set(image, TextureOptions::NEAREST),
-                        None => {
-                            let color_image = Arc::new(ColorImage::new(
-                                [WIDTH, HEIGHT],
-                                Color32::from_black_alpha(0),
-                            ));
-                            let gb_image = ImageData::Color(color_image);
-
-                            let texutre_manager = ctx.tex_manager();
-                            let texture_id = texutre_manager.write().alloc(
-                                "genesis".into(),
-                                gb_image,
-                                TextureOptions::NEAREST,
-                            );
-                            self.gb_texture = Some(TextureHandle::new(texutre_manager, texture_id));
+                while gameboy.video_rec.try_recv().is_ok() {
+                    self.dropped_frames += 1;
+                }
+                self.last_catch_up = Some(Instant::now());
+            }
+            if self.settings.verbose_logging {
+                log::info!("Rendering Frame for: {}", gameboy.rom_info.get_name());
+            }
+            //Only the newest waiting frame is shown, the older ones would just be latency. Frame
+            //advance steps through them one at a time instead
+            let mut received = gameboy.video_rec.try_recv();
+            if received.is_ok() && !self.advancing {
+                for newer in gameboy.video_rec.try_iter() {
+                    self.stats.frame_received();
+                    self.dropped_frames += 1;
+                    received = Ok(newer);
+                }
+            }
+            match received {
+                Ok(buffer_u32) => {
+                    if let Ok(buffer) = bytemuck::try_cast_slice(&buffer_u32) {
+                        let correct = self.display.cgb_color_correction && self.cgb_mode;
+                        let transform = self.color_adjust.transform();
+                        assert_eq!(WIDTH * HEIGHT * 4, buffer.len());
+                        //The texture let go of last frame's image when it was uploaded, so this
+                        //writes over it instead of allocating a new one
+                        let image = Arc::make_mut(&mut self.frame_image);
+                        for (pixel, p) in image.pixels.iter_mut().zip(buffer.chunks_exact(4)) {
+                            let mut rgb = if correct {
+                                correct_cgb_color(p[2], p[1], p[0])
+                            } else {
+                                [p[2], p[1], p[0]]
+                            };
+                            if let Some(transform) = &transform {
+                                rgb = transform.apply(rgb);
+                            }
+                            let [r, g, b] = rgb;
+                            *pixel = Color32::from_rgba_premultiplied(r, g, b, p[3]);
                         }
+                        self.display.apply_scanlines(image);
+                        let image = self.display.filter.prepare(&self.frame_image);
+                        match &mut self.gb_texture {
+                            Some(texture) => texture.set(image, self.display.filter.options()),
+                            None => {
+                                let color_image = Arc::new(ColorImage::new(
+                                    [WIDTH, HEIGHT],
+                                    Color32::from_black_alpha(0),
+                                ));
+                                let gb_image = ImageData::Color(color_image);
+
+                                let texutre_manager = ctx.tex_manager();
+                                let texture_id = texutre_manager.write().alloc(
+                                    //Named per game so it's easy to spot in the texture inspector
+                                    format!("gb_framebuffer_{}", gameboy.rom_info.get_name()),
+                                    gb_image,
+                                    self.display.filter.options(),
+                                );
+                                self.gb_texture =
+                                    Some(TextureHandle::new(texutre_manager, texture_id));
+                            }
+                        }
+                    }
+                    self.last_frame = Some(buffer_u32);
+                    new_frame = true;
+                    self.autofire.next_frame();
+                    self.stats.frame_received();
+                    if self.advancing {
+                        self.advancing = false;
+                        self.audio.pause();
                     }
                 }
+                Err(TryRecvError::Disconnected) => stopped = true,
+                Err(TryRecvError::Empty) => (),
             }
 
             //Update inputs
-            let inputs = self.inputs.get_or_insert_with(|| {
-                Inputs::with_state(Gilrs::new().unwrap(), ctx.clone(), self.input_state.clone())
-            });
-            while let Some(_event) = inputs.gilrs.next_event() {}
-            let mut inputs = inputs.pressed_all();
-            for (i, input) in inputs.iter_mut().enumerate() {
-                if self.input_touch[i] {
-                    *input = true;
+            if !stopped {
+                let inputs = get_inputs(&mut self.inputs, ctx, &self.input_state);
+                inputs.game_viewport = self
+                    .display
+                    .separate_window
+                    .then(DisplayOptions::game_viewport);
+                let mut inputs = inputs.pressed_all();
+                self.autofire.apply(&mut inputs);
+                for (i, input) in inputs.iter_mut().enumerate() {
+                    if self.input_touch[i] {
+                        *input = true;
+                    }
+                }
+                //Once per emulated frame, high refresh rate displays update a lot more often
+                if new_frame {
+                    match gameboy.input_sender.try_send(inputs) {
+                        Ok(()) => {
+                            //Anything latched has been sent now
+                            if let Some(inputs) = &mut self.inputs {
+                                inputs.clear_latched();
+                            }
+                        }
+                        //The emulator is behind, it'll get the next frame's inputs instead
+                        Err(TrySendError::Full(_)) => (),
+                        Err(TrySendError::Disconnected(_)) => stopped = true,
+                    }
                 }
             }
-            gameboy.input_sender.try_send(inputs).unwrap();
+        }
+
+        if stopped {
+            self.emulator_stopped();
+        }
+        //Nothing plays as player 2 yet, so its gamepad events are only wanted while rebinding it
+        let rebinding_p2 = self.button_rebinding.is_some() && self.bindings_player == Player::Two;
+        if let Some(inputs) = self.inputs_p2.as_mut().filter(|_| !rebinding_p2) {
+            inputs.discard_events();
+        }
+        self.display_emulator_error(ctx);
+        self.display_pending_save(ctx);
+        self.display_pending_zip(ctx);
+        if let Some(saves) = &mut self.saves {
+            saves.show_import_conflicts(ctx);
         }
 
         if self.menu_visible {
@@ -496,9 +1686,15 @@ impl eframe::App for TemplateApp {
                         ui.add_space(16.0);
                     }
 
-                    if ui.button(RichText::new("≡").monospace()).clicked() {
-                        self.menu_visible = !self.menu_visible;
-                    }
+                    ui.horizontal(|ui| {
+                        if ui.button(RichText::new("≡").monospace()).clicked() {
+                            self.menu_visible = !self.menu_visible;
+                        }
+                        self.game_buttons(ui);
+                        if self.volume.muted {
+                            ui.label("🔇");
+                        }
+                    });
 
                     egui::widgets::global_dark_light_mode_buttons(ui);
 
@@ -508,6 +1704,12 @@ impl eframe::App for TemplateApp {
                     }
                     ctx.set_style(style);
 
+                    if let Some(gameboy) = &self.gameboy {
+                        let name = gameboy.rom_info.get_name();
+                        let played = self.play_time.get(&name).copied().unwrap_or_default();
+                        ui.label(format!("Playing {name} ({})", format_play_time(played)));
+                    }
+
                     if ui
                         .add_sized([ui.available_width(), 0.0], egui::Button::new("open"))
                         .clicked()
@@ -515,6 +1717,22 @@ impl eframe::App for TemplateApp {
                         self.audio.pause();
                         self.load()
                     }
+                    self.display_recent_roms(ui);
+
+                    if self.gameboy.is_some() {
+                        if ui
+                            .add_sized([ui.available_width(), 0.0], egui::Button::new("rom info"))
+                            .clicked()
+                        {
+                            self.rom_info_visible = !self.rom_info_visible;
+                        }
+
+                        if self.rom_info_visible {
+                            ui.add_space(SPACE_BEFORE);
+                            self.display_rom_info(ui);
+                            ui.add_space(SPACE_AFTER);
+                        }
+                    }
 
                     if ui
                         .add_sized([ui.available_width(), 0.0], egui::Button::new("bootroms"))
@@ -541,12 +1759,51 @@ impl eframe::App for TemplateApp {
 
                     if self.palettes.window_visible {
                         ui.add_space(SPACE_BEFORE);
+                        //The same as picking DMG under bootroms, palettes only apply in DMG mode
+                        let mut force_dmg = self.bootrom_options.gb_type == Some(GameboyType::DMG);
+                        if ui
+                            .checkbox(&mut force_dmg, "Force DMG mode")
+                            .on_hover_text(
+                                "Run GBC games as a plain Gameboy so these palettes apply",
+                            )
+                            .changed()
+                        {
+                            self.bootrom_options.gb_type = force_dmg.then_some(GameboyType::DMG);
+                            self.reload();
+                        }
                         if self.palettes.display_palettes(ui) {
-                            if let Some(gameboy) = &mut self.gameboy {
-                                let pal = self.palettes.get_u32_palette();
-                                gameboy.set_palettes(PaletteColors::new((pal[0], pal[1], pal[2])))
-                            }
+                            self.apply_palette();
                         }
+                        ui.horizontal(|ui| {
+                            if ui.button("Import").clicked() {
+                                open(
+                                    &self.events,
+                                    &[PALETTE_FILTER, ALL_FILES],
+                                    EventType::PaletteUpload,
+                                    &mut self.last_dirs,
+                                );
+                            }
+                            if ui.button("Export").clicked() {
+                                match self.palettes.export() {
+                                    Ok(data) => save_file("palettes.json", PALETTE_FILTER, &data),
+                                    Err(err) => log::error!("Unable to export palettes: {err}"),
+                                }
+                            }
+                        });
+                        self.color_adjust.display(ui);
+                        ui.add_space(SPACE_AFTER);
+                    }
+
+                    if ui
+                        .add_sized([ui.available_width(), 0.0], egui::Button::new("display"))
+                        .clicked()
+                    {
+                        self.display.window_visible = !self.display.window_visible;
+                    }
+
+                    if self.display.window_visible {
+                        ui.add_space(SPACE_BEFORE);
+                        self.display.display_options(ui);
                         ui.add_space(SPACE_AFTER);
                     }
 
@@ -560,11 +1817,32 @@ impl eframe::App for TemplateApp {
                     if self.saves_visible {
                         ui.add_space(SPACE_BEFORE);
                         if let Some(saves) = &mut self.saves {
-                            saves.show_save_manager(ui);
+                            if let Some(gameboy) = &self.gameboy {
+                                let name = gameboy.rom_info.get_name();
+                                if gameboy.rom_info.is_battery_backed()
+                                    && ui.button(format!("Export {name}.sav")).clicked()
+                                {
+                                    saves.export(&name);
+                                }
+                            }
+                            saves.show_save_manager(ui, &mut self.last_dirs);
                         }
                         ui.add_space(SPACE_AFTER);
                     }
 
+                    if ui
+                        .add_sized([ui.available_width(), 0.0], egui::Button::new("speed"))
+                        .clicked()
+                    {
+                        self.speed_visible = !self.speed_visible;
+                    }
+
+                    if self.speed_visible {
+                        ui.add_space(SPACE_BEFORE);
+                        self.display_speed(ctx, ui);
+                        ui.add_space(SPACE_AFTER);
+                    }
+
                     if ui
                         .add_sized([ui.available_width(), 0.0], egui::Button::new("volume"))
                         .clicked()
@@ -578,6 +1856,19 @@ impl eframe::App for TemplateApp {
                         ui.add_space(SPACE_AFTER);
                     }
 
+                    if ui
+                        .add_sized([ui.available_width(), 0.0], egui::Button::new("settings"))
+                        .clicked()
+                    {
+                        self.settings.window_visible = !self.settings.window_visible;
+                    }
+
+                    if self.settings.window_visible {
+                        ui.add_space(SPACE_BEFORE);
+                        self.display_settings(ui);
+                        ui.add_space(SPACE_AFTER);
+                    }
+
                     if ui
                         .add_sized([ui.available_width(), 0.0], egui::Button::new("input"))
                         .clicked()
@@ -604,150 +1895,60 @@ impl eframe::App for TemplateApp {
                 .title_bar(false)
                 .resizable(false)
                 .show(ctx, |ui| {
-                    if ui.button(RichText::new("≡").monospace()).clicked() {
-                        self.menu_visible = !self.menu_visible;
-                    }
+                    ui.horizontal(|ui| {
+                        if ui.button(RichText::new("≡").monospace()).clicked() {
+                            self.menu_visible = !self.menu_visible;
+                        }
+                        self.game_buttons(ui);
+                        if self.volume.muted {
+                            ui.label("🔇");
+                        }
+                    });
                 });
         }
 
-        egui::CentralPanel::default().show(ctx, |ui| {
-            if let Some(gb_texture) = &self.gb_texture {
-                ui.vertical_centered(|ui| {
-                    let gameboy = egui::Image::new(ImageSource::Texture(
-                        SizedTexture::from_handle(gb_texture),
-                    ))
-                    .maintain_aspect_ratio(true)
-                    .fit_to_fraction([1.0, 1.0].into());
-                    ui.add(gameboy);
-                });
+        let separate_window = self.display.separate_window && self.gb_texture.is_some();
+        if separate_window {
+            let mut closed = false;
+            ctx.show_viewport_immediate(
+                DisplayOptions::game_viewport(),
+                egui::ViewportBuilder::default()
+                    .with_title(&self.window_title)
+                    .with_inner_size([WIDTH as f32 * 3.0, HEIGHT as f32 * 3.0]),
+                |ctx, _class| {
+                    egui::CentralPanel::default().show(ctx, |ui| self.display_screen(ui));
+                    closed = ctx.input(|i| i.viewport().close_requested());
+                },
+            );
+            //Closing it puts the game back in the main window
+            if closed {
+                self.display.separate_window = false;
+            }
+        }
 
-                if self.touch_visible {
-                    ui.add_space(16.0);
-
-                    ui.vertical_centered_justified(|ui| {
-                        egui::Grid::new("touch_controls")
-                            .spacing([0.0, 0.0])
-                            .min_col_width(ui.available_width() / 6.0)
-                            .max_col_width(ui.available_width() / 6.0)
-                            .show(ui, |ui| {
-                                const A: usize = 0;
-                                const B: usize = 1;
-                                const RIGHT: usize = 4;
-                                const LEFT: usize = 5;
-                                const UP: usize = 6;
-                                const DOWN: usize = 7;
-
-                                let tile_size = [ui.available_width(), ui.available_width()];
-
-                                self.input_touch = [false; 8];
-
-                                let up_left = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!(
-                                            "../assets/TRANS.png"
-                                        )),
-                                    )
-                                    .contains_pointer();
-                                let up = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!("../assets/UP.png")),
-                                    )
-                                    .contains_pointer();
-                                let up_right = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!(
-                                            "../assets/TRANS.png"
-                                        )),
-                                    )
-                                    .contains_pointer();
-                                ui.end_row();
-
-                                let left = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!(
-                                            "../assets/LEFT.png"
-                                        )),
-                                    )
-                                    .contains_pointer();
-                                ui.add_sized(tile_size, egui::Label::new(""))
-                                    .contains_pointer();
-                                let right = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!(
-                                            "../assets/RIGHT.png"
-                                        )),
-                                    )
-                                    .contains_pointer();
-                                ui.add_sized(tile_size, egui::Label::new(""))
-                                    .contains_pointer();
-                                self.input_touch[B] = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!("../assets/B.png")),
-                                    )
-                                    .contains_pointer();
-                                self.input_touch[A] = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!("../assets/A.png")),
-                                    )
-                                    .contains_pointer();
-                                ui.end_row();
-
-                                let down_left = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!(
-                                            "../assets/TRANS.png"
-                                        )),
-                                    )
-                                    .contains_pointer();
-                                let down = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!(
-                                            "../assets/DOWN.png"
-                                        )),
-                                    )
-                                    .contains_pointer();
-                                let down_right = ui
-                                    .add_sized(
-                                        tile_size,
-                                        egui::Image::new(egui::include_image!(
-                                            "../assets/TRANS.png"
-                                        )),
-                                    )
-                                    .contains_pointer();
-                                ui.end_row();
-                                ui.end_row();
-
-                                self.input_touch[UP] = up_left | up | up_right;
-                                self.input_touch[DOWN] = down_left | down | down_right;
-                                self.input_touch[LEFT] = up_left | left | down_left;
-                                self.input_touch[RIGHT] = up_right | right | down_right;
-                            });
-
-                        ui.vertical_centered(|ui| {
-                            const SELECT: usize = 2;
-                            const START: usize = 3;
-                            self.input_touch[SELECT] = ui
-                                .add_sized([ui.available_width(), 0.0], egui::Button::new("Select"))
-                                .contains_pointer();
-                            self.input_touch[START] = ui
-                                .add_sized([ui.available_width(), 0.0], egui::Button::new("Start"))
-                                .contains_pointer();
-                        });
-                    });
+        egui::CentralPanel::default().show(ctx, |ui| {
+            if self.gb_texture.is_some() {
+                if !separate_window {
+                    self.display_screen(ui);
                 }
+
+                self.input_touch = match self.touch_visible {
+                    true => self.touch.show(ctx, ui),
+                    false => [false; 8],
+                };
             }
         });
 
-        ctx.request_repaint();
+        self.display_indicators(ctx);
+        self.notifications.show(ctx);
+        self.stats.show(ctx);
+
+        //The slow repaint is for gamepads and anything on a timer, like notifications
+        let running = self.gameboy.is_some() && (!self.paused || self.advancing);
+        match running {
+            true => ctx.request_repaint(),
+            false => ctx.request_repaint_after(IDLE_REPAINT),
+        }
     }
 
     fn auto_save_interval(&self) -> std::time::Duration {
@@ -759,6 +1960,37 @@ impl eframe::App for TemplateApp {
     }
 }
 
+//A volume slider with a checkbox to turn the channel off, returns true if either changed
+fn channel_volume(ui: &mut egui::Ui, name: &str, volume: &mut u32, enabled: &mut bool) -> bool {
+    const VOLUME_RANGE: RangeInclusive<u32> = 0..=100;
+    ui.horizontal(|ui| {
+        let toggled = ui.checkbox(enabled, "").changed();
+        let slid = ui
+            .add_enabled(*enabled, egui::Slider::new(volume, VOLUME_RANGE).text(name))
+            .changed();
+        toggled | slid
+    })
+    .inner
+}
+
+fn get_inputs<'a>(
+    inputs: &'a mut Option<Inputs>,
+    ctx: &egui::Context,
+    state: &InputsState,
+) -> &'a mut Inputs {
+    inputs.get_or_insert_with(|| Inputs::with_state(init_gilrs(), ctx.clone(), state.clone()))
+}
+
+fn format_play_time(seconds: f64) -> String {
+    let seconds = seconds as u64;
+    let (hours, minutes) = (seconds / 3600, (seconds / 60) % 60);
+    if hours > 0 {
+        format!("{hours}h {minutes:02}m")
+    } else {
+        format!("{minutes}m {:02}s", seconds % 60)
+    }
+}
+
 fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
     ui.horizontal(|ui| {
         ui.spacing_mut().item_spacing.x = 0.0;
@@ -774,12 +2006,22 @@ fn powered_by_egui_and_eframe(ui: &mut egui::Ui) {
 }
 
 #[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
 struct Volume {
     pub master: u32,
+    pub muted: bool,
     pub square_1: u32,
     pub square_2: u32,
     pub wave: u32,
     pub noise: u32,
+    pub square_1_enabled: bool,
+    pub square_2_enabled: bool,
+    pub wave_enabled: bool,
+    pub noise_enabled: bool,
+    pub limiter: bool,
+    //By name, None for the system default
+    pub output_device: Option<String>,
+    pub latency: Latency,
     pub window_visible: bool,
 }
 
@@ -787,10 +2029,74 @@ impl Default for Volume {
     fn default() -> Self {
         Self {
             master: 100,
+            muted: false,
             square_1: 100,
             square_2: 100,
             wave: 100,
             noise: 100,
+            square_1_enabled: true,
+            square_2_enabled: true,
+            wave_enabled: true,
+            noise_enabled: true,
+            limiter: false,
+            output_device: None,
+            latency: Latency::default(),
+            window_visible: false,
+        }
+    }
+}
+
+impl Volume {
+    fn master_output(&self) -> u8 {
+        if self.muted {
+            0
+        } else {
+            self.master as u8
+        }
+    }
+
+    //solgb only has per channel volume, so disabled channels are set to 0 rather than turned off
+    fn apply_channels(&self, audio_control: &AudioControl) {
+        let channel = |volume: u32, enabled: bool| if enabled { volume as f32 } else { 0.0 };
+        audio_control.set_volume(
+            Channel::Square1,
+            channel(self.square_1, self.square_1_enabled),
+        );
+        audio_control.set_volume(
+            Channel::Square2,
+            channel(self.square_2, self.square_2_enabled),
+        );
+        audio_control.set_volume(Channel::Wave, channel(self.wave, self.wave_enabled));
+        audio_control.set_volume(Channel::Noise, channel(self.noise, self.noise_enabled));
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+struct Settings {
+    pub all_files_default: bool,
+    //Log every frame, off by default since it floods the console
+    pub verbose_logging: bool,
+    //How many frames can queue up before we skip ahead to the newest one
+    pub max_frame_backlog: usize,
+    //Hold quick taps until the emulator has seen them instead of sampling once per repaint
+    pub latch_inputs: bool,
+    pub pause_on_focus_loss: bool,
+    pub window_visible: bool,
+}
+
+impl Settings {
+    const BACKLOG_RANGE: RangeInclusive<usize> = 1..=240;
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            all_files_default: false,
+            verbose_logging: false,
+            max_frame_backlog: 60,
+            latch_inputs: false,
+            pause_on_focus_loss: false,
             window_visible: false,
         }
     }
@@ -814,6 +2120,38 @@ impl BootRomOptions {
     }
 }
 
+//Directories the file dialogs were last opened in, only used on native
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct LastDirs {
+    rom: Option<PathBuf>,
+    save: Option<PathBuf>,
+    bootrom: Option<PathBuf>,
+    palette: Option<PathBuf>,
+}
+
+impl LastDirs {
+    #[cfg(not(target_arch = "wasm32"))]
+    fn get(&self, event_type: EventType) -> Option<&PathBuf> {
+        match event_type {
+            EventType::OpenRom => self.rom.as_ref(),
+            EventType::SaveUpload | EventType::SaveZipUpload => self.save.as_ref(),
+            EventType::BootromUpload(_) => self.bootrom.as_ref(),
+            EventType::PaletteUpload => self.palette.as_ref(),
+        }
+    }
+
+    #[cfg(not(target_arch = "wasm32"))]
+    fn set(&mut self, event_type: EventType, dir: PathBuf) {
+        match event_type {
+            EventType::OpenRom => self.rom = Some(dir),
+            EventType::SaveUpload | EventType::SaveZipUpload => self.save = Some(dir),
+            EventType::BootromUpload(_) => self.bootrom = Some(dir),
+            EventType::PaletteUpload => self.palette = Some(dir),
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct Events(Rc<RefCell<VecDeque<Event>>>);
 
@@ -834,21 +2172,51 @@ impl Default for Events {
 }
 
 pub enum Event {
-    OpenRom(Vec<u8>),
+    //The rom and, on native, where it was read from
+    OpenRom(Vec<u8>, Option<PathBuf>),
     SaveUpload(String, Vec<u8>),
+    //A zip of saves, like the one Download All makes
+    SaveZipUpload(Vec<u8>),
     BootromUpload(GameboyType, Vec<u8>),
+    PaletteUpload(Vec<u8>),
     Resume,
 }
 
+//A zip with several ROMs in it waiting on the user to pick one
+struct PendingZip {
+    roms: Vec<(String, Vec<u8>)>,
+    path: Option<PathBuf>,
+}
+
+struct PendingSave {
+    name: String,
+    data: Vec<u8>,
+    expected: usize,
+}
+
+#[derive(Serialize, Deserialize)]
+struct RecentRom {
+    name: String,
+    //The file on native, the storage key on web
+    location: PathBuf,
+}
+
 #[derive(Copy, Clone)]
 pub(crate) enum EventType {
     OpenRom,
     SaveUpload,
+    SaveZipUpload,
     BootromUpload(GameboyType),
+    PaletteUpload,
 }
 
 #[cfg(target_arch = "wasm32")]
-pub(crate) fn open(events: &Events, filter: &[(&str, &[&str])], event_type: EventType) {
+pub(crate) fn open(
+    events: &Events,
+    filter: &[(&str, &[&str])],
+    event_type: EventType,
+    _last_dirs: &mut LastDirs,
+) {
     use rfd::AsyncFileDialog;
 
     hide_canvas();
@@ -867,11 +2235,13 @@ pub(crate) fn open(events: &Events, filter: &[(&str, &[&str])], event_type: Even
         if let Some(file) = file {
             let data = file.read().await;
             match event_type {
-                EventType::OpenRom => events.push(Event::OpenRom(data)),
+                EventType::OpenRom => events.push(Event::OpenRom(data, None)),
                 EventType::SaveUpload => events.push(Event::SaveUpload(file.file_name(), data)),
+                EventType::SaveZipUpload => events.push(Event::SaveZipUpload(data)),
                 EventType::BootromUpload(gb_type) => {
                     events.push(Event::BootromUpload(gb_type, data))
                 }
+                EventType::PaletteUpload => events.push(Event::PaletteUpload(data)),
             }
         } else {
             events.push(Event::Resume);
@@ -883,34 +2253,73 @@ pub(crate) fn open(events: &Events, filter: &[(&str, &[&str])], event_type: Even
 }
 
 #[cfg(not(target_arch = "wasm32"))]
-pub(crate) fn open(events: &Events, filter: &[(&str, &[&str])], event_type: EventType) {
+pub(crate) fn open(
+    events: &Events,
+    filter: &[(&str, &[&str])],
+    event_type: EventType,
+    last_dirs: &mut LastDirs,
+) {
     use rfd::FileDialog;
 
     let mut file_dialog = FileDialog::new();
     for (name, extensions) in filter {
         file_dialog = file_dialog.add_filter(*name, extensions);
     }
-    file_dialog = file_dialog.set_directory("/");
+    file_dialog = match last_dirs.get(event_type) {
+        Some(dir) => file_dialog.set_directory(dir),
+        None => file_dialog.set_directory("/"),
+    };
 
     if let Some(file) = file_dialog.pick_file() {
+        if let Some(dir) = file.parent() {
+            last_dirs.set(event_type, dir.to_path_buf());
+        }
         let name = file
             .file_name()
             .unwrap_or_default()
             .to_str()
             .unwrap_or_default()
             .to_owned();
-        if let Ok(data) = std::fs::read(file) {
+        if let Ok(data) = std::fs::read(&file) {
             match event_type {
-                EventType::OpenRom => events.push(Event::OpenRom(data)),
+                EventType::OpenRom => events.push(Event::OpenRom(data, Some(file))),
                 EventType::SaveUpload => events.push(Event::SaveUpload(name, data)),
+                EventType::SaveZipUpload => events.push(Event::SaveZipUpload(data)),
                 EventType::BootromUpload(gb_type) => {
                     events.push(Event::BootromUpload(gb_type, data))
                 }
+                EventType::PaletteUpload => events.push(Event::PaletteUpload(data)),
             }
         }
     }
 }
 
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn save_file(name: &str, _filter: (&str, &[&str]), data: &[u8]) {
+    use base64::{engine::general_purpose::STANDARD, Engine as _};
+
+    if let Err(err) = Saves::download_helper(name, &STANDARD.encode(data)) {
+        log::error!("Unable to download {name}: {err}");
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn save_file(name: &str, filter: (&str, &[&str]), data: &[u8]) {
+    use rfd::FileDialog;
+
+    let Some(file) = FileDialog::new()
+        .add_filter(filter.0, filter.1)
+        .set_file_name(name)
+        .save_file()
+    else {
+        return;
+    };
+
+    if let Err(err) = std::fs::write(&file, data) {
+        log::error!("Unable to write {}: {err}", file.display());
+    }
+}
+
 //We have to hide the canvas while opening files because in some browsers the buttons don't work
 fn hide_canvas() {
     #[cfg(target_arch = "wasm32")]
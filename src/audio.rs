@@ -1,6 +1,7 @@
+use std::cell::Cell;
 use std::sync::{
-    atomic::{AtomicU8, Ordering},
-    Arc,
+    atomic::{AtomicBool, AtomicU8, Ordering},
+    Arc, Weak,
 };
 
 use cpal::{
@@ -8,48 +9,239 @@ use cpal::{
     Device, FromSample, SizedSample, Stream, StreamConfig, SupportedStreamConfig,
 };
 use crossbeam_channel::{Receiver, Sender};
+use rtrb::{Consumer, RingBuffer};
+use serde::{Deserialize, Serialize};
 use solgb::AudioControl;
 #[cfg(not(target_arch = "wasm32"))]
+use std::thread;
+#[cfg(not(target_arch = "wasm32"))]
 use std::time::{Duration, Instant};
 #[cfg(target_arch = "wasm32")]
+use wasm_thread as thread;
+#[cfg(target_arch = "wasm32")]
 use web_time::{Duration, Instant};
 
+//A stereo sample
+type Frame = [f32; 2];
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Latency {
+    Low,
+    //Lets the device pick its buffer size
+    #[default]
+    Medium,
+    High,
+}
+
+impl Latency {
+    pub const ALL: [Latency; 3] = [Latency::Low, Latency::Medium, Latency::High];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Latency::Low => "Low",
+            Latency::Medium => "Medium",
+            Latency::High => "High",
+        }
+    }
+
+    //Frames per callback, None leaves it up to the device
+    fn buffer_frames(&self) -> Option<u32> {
+        match self {
+            Latency::Low => Some(256),
+            Latency::Medium => None,
+            Latency::High => Some(4096),
+        }
+    }
+
+    //How far the feeder can get ahead of the callback, on top of the device's own buffer
+    fn ring_time(&self) -> Duration {
+        match self {
+            Latency::Low => Duration::from_millis(20),
+            Latency::Medium => Duration::from_millis(40),
+            Latency::High => Duration::from_millis(100),
+        }
+    }
+}
+
 pub struct Audio {
-    pub device: Device,
-    pub config: SupportedStreamConfig,
+    //None when there is no usable output device, audio is disabled and everything else is a no-op
+    output: Option<(Device, SupportedStreamConfig)>,
+    //Names of every output device, for picking one in the volume panel
+    device_names: Vec<String>,
+    latency: Latency,
     stream: Option<Stream>,
+    //Only used while disabled, so samples still get pulled out of the emulator
+    playing: bool,
+    warned: Cell<bool>,
     volume: Arc<AtomicU8>,
-    ac_receiver: Receiver<AudioControl>,
-    ac_sender: Sender<AudioControl>,
+    //Softly squash loud output instead of letting it clip
+    limiter: Arc<AtomicBool>,
+    //Percent of normal speed. Below 100 each sample plays for longer, and since the emulator is
+    //paced by us taking samples that slows the game down with it
+    speed: Arc<AtomicU8>,
+    //New feeds from start_feeder are sent to the callback through here, the callback reads the
+    //ring directly after that
+    feed_receiver: Receiver<Consumer<Frame>>,
+    feed_sender: Sender<Consumer<Frame>>,
+    //The feeder thread stops once this is dropped, even if its ring is full
+    feeder_alive: Option<Arc<()>>,
     audio_control: Option<AudioControl>,
+    //While set the callback sends a copy of everything it plays to rec_receiver
+    recording: Arc<AtomicBool>,
+    record_started: Option<Instant>,
+    rec_sender: Sender<Vec<f32>>,
+    rec_receiver: Receiver<Vec<f32>>,
 }
 
 impl Audio {
-    pub fn new() -> Self {
-        let host = cpal::default_host();
-        let device = host.default_output_device().unwrap();
-        log::info!("Output device: {}", device.name().unwrap());
-        let config = device.default_output_config().unwrap();
-        log::info!("Default output config: {:?}", config);
-
+    /// Uses the output device called `device_name` if it's there, otherwise the default one.
+    pub fn new(device_name: Option<&str>, latency: Latency) -> Self {
         let volume = Arc::new(AtomicU8::new(0));
-        let (ac_sender, ac_receiver) = crossbeam_channel::unbounded();
+        let (feed_sender, feed_receiver) = crossbeam_channel::unbounded();
+        let (rec_sender, rec_receiver) = crossbeam_channel::unbounded();
 
         let mut audio = Self {
-            device,
-            config,
+            output: Self::find_output(device_name),
+            device_names: Self::output_device_names(),
+            latency,
             stream: None,
+            playing: false,
+            warned: Cell::new(false),
             volume,
-            ac_receiver,
-            ac_sender,
+            limiter: Arc::new(AtomicBool::new(false)),
+            speed: Arc::new(AtomicU8::new(100)),
+            feed_receiver,
+            feed_sender,
+            feeder_alive: None,
             audio_control: None,
+            recording: Arc::new(AtomicBool::new(false)),
+            record_started: None,
+            rec_sender,
+            rec_receiver,
         };
         audio.setup_stream();
         audio
     }
 
+    fn output_device_names() -> Vec<String> {
+        match cpal::default_host().output_devices() {
+            Ok(devices) => devices.filter_map(|device| device.name().ok()).collect(),
+            Err(err) => {
+                log::error!("Unable to list output devices: {err}");
+                Vec::new()
+            }
+        }
+    }
+
+    fn find_output(device_name: Option<&str>) -> Option<(Device, SupportedStreamConfig)> {
+        let host = cpal::default_host();
+        let chosen = device_name.and_then(|name| {
+            let device = host
+                .output_devices()
+                .ok()?
+                .find(|device| device.name().is_ok_and(|n| n == name));
+            if device.is_none() {
+                log::info!("Output device {name} is not available, using the default");
+            }
+            device
+        });
+        let Some(device) = chosen.or_else(|| host.default_output_device()) else {
+            log::error!("No audio output device found, audio is disabled");
+            return None;
+        };
+        log::info!(
+            "Output device: {}",
+            device.name().unwrap_or("unknown".to_string())
+        );
+        let config = match device.default_output_config() {
+            Ok(config) => config,
+            Err(err) => {
+                log::error!("Unable to get an output config ({err}), audio is disabled");
+                return None;
+            }
+        };
+        log::info!("Default output config: {:?}", config);
+        Some((device, config))
+    }
+
+    pub fn device_names(&self) -> &[String] {
+        &self.device_names
+    }
+
+    //Rebuilds the stream on another device, it has to be played again afterwards
+    pub fn set_device(&mut self, device_name: Option<&str>) {
+        self.stream = None;
+        self.output = Self::find_output(device_name);
+        self.setup_stream();
+    }
+
+    //Like set_device, the stream is rebuilt and has to be played again
+    pub fn set_latency(&mut self, latency: Latency) {
+        self.stream = None;
+        self.latency = latency;
+        self.setup_stream();
+    }
+
+    pub fn is_disabled(&self) -> bool {
+        self.output.is_none()
+    }
+
+    //Only complain about being disabled the first time something tries to use audio
+    fn warn_disabled(&self) {
+        if !self.warned.replace(true) {
+            log::warn!("Audio is disabled, there is no output device");
+        }
+    }
+
+    //Without a stream nothing takes samples out of the emulator, so throw them away to keep it running
+    pub fn discard_samples(&self) {
+        if !self.is_disabled() || !self.playing {
+            return;
+        }
+        if let Some(audio_control) = &self.audio_control {
+            while audio_control.try_get_audio_buffer().is_ok() {}
+        }
+    }
+
+    pub fn set_limiter(&self, enabled: bool) {
+        self.limiter.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn set_speed(&self, percent: u8) {
+        self.speed.store(percent.clamp(1, 100), Ordering::Relaxed);
+    }
+
+    pub fn start_recording(&mut self) {
+        if self.is_disabled() {
+            self.warn_disabled();
+            return;
+        }
+        while self.rec_receiver.try_recv().is_ok() {}
+        self.record_started = Some(Instant::now());
+        self.recording.store(true, Ordering::Relaxed);
+    }
+
+    /// Stops recording and returns what was recorded as a 16 bit stereo WAV file.
+    pub fn stop_recording(&mut self) -> Option<Vec<u8>> {
+        self.record_started.take()?;
+        self.recording.store(false, Ordering::Relaxed);
+        let samples: Vec<f32> = self.rec_receiver.try_iter().flatten().collect();
+        let sample_rate = self.output.as_ref()?.1.sample_rate().0;
+        Some(encode_wav(&samples, sample_rate))
+    }
+
+    pub fn recording_time(&self) -> Option<Duration> {
+        self.record_started.map(|started| started.elapsed())
+    }
+
     fn setup_stream(&mut self) {
-        self.stream = match self.config.sample_format() {
+        //The ring is moved into the callback, so a new stream needs a new ring and feeder
+        self.stream = None;
+        self.start_feeder();
+        let Some(sample_format) = self.output.as_ref().map(|(_, c)| c.sample_format()) else {
+            return;
+        };
+        self.stream = match sample_format {
             cpal::SampleFormat::I8 => self.setup::<i8>(),
             cpal::SampleFormat::I16 => self.setup::<i16>(),
             cpal::SampleFormat::I32 => self.setup::<i32>(),
@@ -65,6 +257,12 @@ impl Audio {
     }
 
     pub fn play(&mut self) {
+        if self.is_disabled() {
+            self.warn_disabled();
+            self.playing = true;
+            return;
+        }
+
         self.setup_stream();
 
         let Some(stream) = &self.stream else {
@@ -77,7 +275,13 @@ impl Audio {
         }
     }
 
-    pub fn pause(&self) {
+    pub fn pause(&mut self) {
+        if self.is_disabled() {
+            self.warn_disabled();
+            self.playing = false;
+            return;
+        }
+
         let Some(stream) = &self.stream else {
             log::error!("Failed to pause stream: Stream is not setup (bad device/config?)");
             return;
@@ -88,14 +292,83 @@ impl Audio {
         }
     }
 
+    /// Lets go of the emulator's audio so nothing keeps it alive after the game is closed
+    pub fn clear_audio_control(&mut self) {
+        self.stream = None;
+        self.feeder_alive = None;
+        self.audio_control = None;
+        self.playing = false;
+    }
+
     pub fn set_audio_control(&mut self, audio_control: AudioControl) {
-        self.audio_control = Some(audio_control.clone());
-        if let Err(err) = self.ac_sender.send(audio_control) {
-            log::error!("Unable to send AudioControl to callback: {err}");
+        self.audio_control = Some(audio_control);
+        self.start_feeder();
+    }
+
+    //Moves samples from the emulator into a ring buffer the callback drains without waiting or
+    //locking. Nothing is taken from the emulator while the ring is full, so it's still paced by
+    //playback
+    fn start_feeder(&mut self) {
+        //Dropping this stops the old feeder, and any ring the callback hasn't picked up is stale
+        self.feeder_alive = None;
+        while self.feed_receiver.try_recv().is_ok() {}
+        let Some(audio_control) = self.audio_control.clone() else {
+            return;
+        };
+        let Some((_, config)) = &self.output else {
+            return;
+        };
+
+        let ring_time = self.latency.ring_time();
+        let frames = config.sample_rate().0 as usize * ring_time.as_millis() as usize / 1000;
+        let (mut producer, consumer) = RingBuffer::<Frame>::new(frames.max(1));
+        //Checking a few times per ring's worth of audio keeps it topped up without spinning
+        let wait = ring_time / 4;
+        let alive = Arc::new(());
+        let feeder: Weak<()> = Arc::downgrade(&alive);
+        thread::spawn(move || {
+            //What's left of the last buffer the emulator gave us
+            let mut pending = Vec::new();
+            let mut taken = 0;
+            while feeder.strong_count() > 0 && !producer.is_abandoned() {
+                if pending.len() - taken < 2 {
+                    match audio_control.try_get_audio_buffer() {
+                        Ok(samples) => {
+                            pending.clear();
+                            pending.extend_from_slice(&samples);
+                            taken = 0;
+                        }
+                        Err(_) => thread::sleep(wait),
+                    }
+                    continue;
+                }
+
+                let count = ((pending.len() - taken) / 2).min(producer.slots());
+                let chunk = match producer.write_chunk_uninit(count) {
+                    Ok(chunk) if count > 0 => chunk,
+                    //The ring is full, the callback needs to catch up
+                    _ => {
+                        thread::sleep(wait);
+                        continue;
+                    }
+                };
+                let frames = pending[taken..].chunks_exact(2);
+                chunk.fill_from_iter(frames.map(|frame| [frame[0], frame[1]]));
+                taken += count * 2;
+            }
+        });
+
+        self.feeder_alive = Some(alive);
+        if let Err(err) = self.feed_sender.send(consumer) {
+            log::error!("Unable to send samples to callback: {err}");
         }
     }
 
     pub fn set_volume(&self, mut volume: u8) {
+        if self.is_disabled() {
+            self.warn_disabled();
+            return;
+        }
         if volume > 100 {
             volume = 100;
         }
@@ -106,63 +379,124 @@ impl Audio {
     where
         T: SizedSample + FromSample<f32>,
     {
-        const TIMEOUT: Duration = Duration::from_millis(20);
-
-        let config: StreamConfig = self.config.clone().into();
+        let (device, supported) = self.output.as_ref()?;
+        let mut config: StreamConfig = supported.clone().into();
+        if let Some(frames) = self.latency.buffer_frames() {
+            let frames = match supported.buffer_size() {
+                cpal::SupportedBufferSize::Range { min, max } => frames.clamp(*min, *max),
+                cpal::SupportedBufferSize::Unknown => frames,
+            };
+            config.buffer_size = cpal::BufferSize::Fixed(frames);
+        }
         log::info!("Actual output config: {:?}", config);
-        let mut last = 0f32;
         let volume = self.volume.clone();
-        let ac_receiver = self.ac_receiver.clone();
-        let mut audio_control = self.audio_control.clone();
-
-        match config.channels {
-            2 => {
-                self.device.build_output_stream(
-                    &config,
-                    {
-                        let mut buffer = Vec::new().into_iter();
-                        move |out: &mut [T], _: &cpal::OutputCallbackInfo| {
-                            if let Ok(ac) = ac_receiver.try_recv() {
-                                log::info!("Loaded new AudioControl");
-                                audio_control = Some(ac);
+        let limiter = self.limiter.clone();
+        let speed = self.speed.clone();
+        let feed_receiver = self.feed_receiver.clone();
+        let mut feed = None;
+        let recording = self.recording.clone();
+        let rec_sender = self.rec_sender.clone();
+
+        //solgb gives us interleaved stereo, this is how many samples make up one frame on the device
+        let channels = config.channels as usize;
+        if channels == 0 {
+            log::error!("Output device has no channels");
+            return None;
+        }
+
+        device
+            .build_output_stream(
+                &config,
+                {
+                    //The frame being played and how far through it we are, for slow motion
+                    let mut held: Frame = [0.0; 2];
+                    let mut phase = 1.0f32;
+                    move |out: &mut [T], _: &cpal::OutputCallbackInfo| {
+                        if let Some(new_feed) = feed_receiver.try_iter().last() {
+                            log::debug!("Loaded new AudioControl");
+                            feed = Some(new_feed);
+                        }
+
+                        let Some(samples) = &mut feed else {
+                            out.fill(T::from_sample(0.0));
+                            return;
+                        };
+
+                        let mut recorded = Vec::new();
+                        let record = recording.load(Ordering::Relaxed);
+                        let step = speed.load(Ordering::Relaxed) as f32 / 100.0;
+                        for frame in out.chunks_mut(channels) {
+                            phase += step;
+                            if phase >= 1.0 {
+                                phase -= 1.0;
+                                //Underruns are silent rather than waiting on the emulator
+                                held = samples.pop().unwrap_or([0.0; 2]);
+                            }
+                            let stereo = held;
+                            if record {
+                                recorded.extend_from_slice(&stereo);
                             }
 
-                            let Some(sample_rec) = &audio_control else {
-                                out.fill(T::from_sample(0.0));
-                                return;
-                            };
-
-                            for value in out.iter_mut() {
-                                last = match buffer.next() {
-                                    Some(val) => val,
-                                    None => {
-                                        let start = Instant::now();
-                                        loop {
-                                            //This jank is because we can't block
-                                            if let Ok(samples) = sample_rec.try_get_audio_buffer() {
-                                                buffer = samples.into_iter();
-                                                break;
-                                            }
-                                            if Instant::now().duration_since(start) > TIMEOUT {
-                                                return;
-                                            }
-                                        }
-                                        buffer.next().unwrap_or(last)
-                                    }
-                                };
-                                let volume = (volume.load(Ordering::Relaxed) as f32) / 100.0;
-                                *value = T::from_sample(last * volume);
+                            let volume = (volume.load(Ordering::Relaxed) as f32) / 100.0;
+                            let limit = limiter.load(Ordering::Relaxed);
+                            let [left, right] = stereo.map(|sample| {
+                                //tanh is close to linear for quiet sounds and never goes past 1.0
+                                if limit {
+                                    (sample * volume).tanh()
+                                } else {
+                                    sample * volume
+                                }
+                            });
+                            match frame {
+                                [mono] => *mono = T::from_sample((left + right) / 2.0),
+                                [l, r, rest @ ..] => {
+                                    *l = T::from_sample(left);
+                                    *r = T::from_sample(right);
+                                    //Surround setups only get the front left/right speakers
+                                    rest.fill(T::from_sample(0.0));
+                                }
+                                [] => (),
                             }
                         }
-                    },
-                    move |err| {
-                        log::error!("Audio callback error: {}", err);
-                    },
-                    None,
-                )
-            }
-            _ => panic!(),
-        }
-        .ok()
+
+                        if !recorded.is_empty() {
+                            let _ = rec_sender.send(recorded);
+                        }
+                    }
+                },
+                move |err| {
+                    log::error!("Audio callback error: {}", err);
+                },
+                None,
+            )
+            .ok()
+    }
+}
+
+//Interleaved stereo samples to a 16 bit PCM WAV file
+fn encode_wav(samples: &[f32], sample_rate: u32) -> Vec<u8> {
+    const CHANNELS: u16 = 2;
+    const BITS: u16 = 16;
+    let block_align = CHANNELS * BITS / 8;
+    let data_len = (samples.len() * 2) as u32;
+
+    let mut wav = Vec::with_capacity(44 + data_len as usize);
+    wav.extend_from_slice(b"RIFF");
+    wav.extend_from_slice(&(36 + data_len).to_le_bytes());
+    wav.extend_from_slice(b"WAVE");
+    wav.extend_from_slice(b"fmt ");
+    wav.extend_from_slice(&16u32.to_le_bytes());
+    wav.extend_from_slice(&1u16.to_le_bytes()); //PCM
+    wav.extend_from_slice(&CHANNELS.to_le_bytes());
+    wav.extend_from_slice(&sample_rate.to_le_bytes());
+    wav.extend_from_slice(&(sample_rate * block_align as u32).to_le_bytes());
+    wav.extend_from_slice(&block_align.to_le_bytes());
+    wav.extend_from_slice(&BITS.to_le_bytes());
+    wav.extend_from_slice(b"data");
+    wav.extend_from_slice(&data_len.to_le_bytes());
+    for sample in samples {
+        let sample = (sample.clamp(-1.0, 1.0) * i16::MAX as f32) as i16;
+        wav.extend_from_slice(&sample.to_le_bytes());
     }
+    wav
 }
@@ -0,0 +1,329 @@
+use egui::{
+    Color32, ColorImage, Image, Painter, Pos2, Rect, Stroke, TextureOptions, Vec2, ViewportId,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+//How much SharpLinear upscales by before the linear filter is applied
+const SHARP_SCALE: usize = 4;
+
+//Every pixel or every tile
+pub const GRID_SPACINGS: [usize; 2] = [1, 8];
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TextureFilter {
+    #[default]
+    Nearest,
+    Linear,
+    //Upscale with nearest first so the linear filter only softens the edges between pixels
+    SharpLinear,
+}
+
+impl TextureFilter {
+    pub const ALL: [TextureFilter; 3] = [
+        TextureFilter::Nearest,
+        TextureFilter::Linear,
+        TextureFilter::SharpLinear,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            TextureFilter::Nearest => "Nearest",
+            TextureFilter::Linear => "Linear",
+            TextureFilter::SharpLinear => "Sharp Linear",
+        }
+    }
+
+    pub fn options(&self) -> TextureOptions {
+        match self {
+            TextureFilter::Nearest => TextureOptions::NEAREST,
+            TextureFilter::Linear | TextureFilter::SharpLinear => TextureOptions::LINEAR,
+        }
+    }
+
+    //Only sharp linear makes a new image, the others hand back the one they're given
+    pub fn prepare(&self, image: &Arc<ColorImage>) -> Arc<ColorImage> {
+        match self {
+            TextureFilter::SharpLinear => Arc::new(upscale(image, SHARP_SCALE)),
+            TextureFilter::Nearest | TextureFilter::Linear => image.clone(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ScaleMode {
+    //Fit the panel while keeping the screen's 10:9 shape
+    #[default]
+    Aspect,
+    Stretch,
+    //The largest whole multiple of the screen size that fits, so every pixel is the same size
+    Integer,
+}
+
+impl ScaleMode {
+    pub const ALL: [ScaleMode; 3] = [ScaleMode::Aspect, ScaleMode::Stretch, ScaleMode::Integer];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScaleMode::Aspect => "Aspect",
+            ScaleMode::Stretch => "Stretch",
+            ScaleMode::Integer => "Integer",
+        }
+    }
+
+    pub fn size<'a>(&self, image: Image<'a>, available: Vec2, pixels_per_point: f32) -> Image<'a> {
+        match self {
+            ScaleMode::Aspect => image
+                .maintain_aspect_ratio(true)
+                .fit_to_fraction([1.0, 1.0].into()),
+            ScaleMode::Stretch => image
+                .maintain_aspect_ratio(false)
+                .fit_to_exact_size(available),
+            ScaleMode::Integer => {
+                //Worked out in physical pixels so the multiple holds with display scaling
+                let screen = Vec2::new(crate::app::WIDTH as f32, crate::app::HEIGHT as f32);
+                let fits = available * pixels_per_point / screen;
+                let scale = fits.x.min(fits.y).floor().max(1.0);
+                image.fit_to_exact_size(screen * scale / pixels_per_point)
+            }
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct DisplayOptions {
+    pub filter: TextureFilter,
+    pub scale: ScaleMode,
+    pub cgb_color_correction: bool,
+    pub scanlines: bool,
+    //How much darker every other row gets, 0.0 to 1.0
+    pub scanline_strength: f32,
+    pub grid: bool,
+    //In gameboy pixels, one of GRID_SPACINGS
+    pub grid_spacing: usize,
+    pub grid_color: Color32,
+    //Show the game in a window of its own, leaving the main one for the menu
+    pub separate_window: bool,
+    pub window_visible: bool,
+}
+
+impl Default for DisplayOptions {
+    fn default() -> Self {
+        Self {
+            filter: TextureFilter::default(),
+            scale: ScaleMode::default(),
+            cgb_color_correction: false,
+            scanlines: false,
+            scanline_strength: 0.5,
+            grid: false,
+            grid_spacing: 8,
+            grid_color: Color32::from_white_alpha(40),
+            separate_window: false,
+            window_visible: false,
+        }
+    }
+}
+
+impl DisplayOptions {
+    pub fn display_options(&mut self, ui: &mut egui::Ui) {
+        ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+            ui.monospace("Filter: ");
+            for filter in TextureFilter::ALL {
+                ui.radio_value(&mut self.filter, filter, filter.label());
+            }
+        });
+        ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+            ui.monospace("Scale:  ");
+            for scale in ScaleMode::ALL {
+                ui.radio_value(&mut self.scale, scale, scale.label());
+            }
+        });
+        ui.checkbox(&mut self.cgb_color_correction, "GBC color correction")
+            .on_hover_text("Mix the colors like a GBC screen does, only affects GBC games");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.scanlines, "Scanlines");
+            ui.add_enabled(
+                self.scanlines,
+                egui::Slider::new(&mut self.scanline_strength, 0.0..=1.0)
+                    .custom_formatter(|value, _| format!("{:.0}%", value * 100.0))
+                    .text("Strength"),
+            );
+        });
+        ui.checkbox(&mut self.separate_window, "Show the game in its own window");
+        ui.horizontal(|ui| {
+            ui.checkbox(&mut self.grid, "Pixel grid");
+            ui.add_enabled_ui(self.grid, |ui| {
+                for spacing in GRID_SPACINGS {
+                    ui.radio_value(&mut self.grid_spacing, spacing, format!("{spacing}px"));
+                }
+                ui.color_edit_button_srgba(&mut self.grid_color);
+            });
+        });
+    }
+
+    pub fn game_viewport() -> ViewportId {
+        ViewportId::from_hash_of("game")
+    }
+
+    /// Draws the grid over the displayed frame, `rect` being where the image ended up.
+    /// Lines are snapped to physical pixels so they stay sharp at any scale.
+    pub fn paint_grid(&self, painter: &Painter, rect: Rect) {
+        if !self.grid {
+            return;
+        }
+        let pixels_per_point = painter.ctx().pixels_per_point();
+        let snap = |value: f32| (value * pixels_per_point).round() / pixels_per_point;
+        let stroke = Stroke::new(1.0 / pixels_per_point, self.grid_color);
+        let spacing = self.grid_spacing.max(1);
+        let (width, height) = (crate::app::WIDTH, crate::app::HEIGHT);
+
+        for column in (spacing..width).step_by(spacing) {
+            let x = snap(rect.left() + rect.width() * column as f32 / width as f32);
+            painter.line_segment(
+                [Pos2::new(x, rect.top()), Pos2::new(x, rect.bottom())],
+                stroke,
+            );
+        }
+        for row in (spacing..height).step_by(spacing) {
+            let y = snap(rect.top() + rect.height() * row as f32 / height as f32);
+            painter.line_segment(
+                [Pos2::new(rect.left(), y), Pos2::new(rect.right(), y)],
+                stroke,
+            );
+        }
+    }
+
+    //Darkens every other row of the frame, does nothing when scanlines are off
+    pub fn apply_scanlines(&self, image: &mut ColorImage) {
+        if !self.scanlines || self.scanline_strength <= 0.0 {
+            return;
+        }
+        let keep = 1.0 - self.scanline_strength.clamp(0.0, 1.0);
+        let width = image.size[0];
+        for row in image.pixels.chunks_exact_mut(width).skip(1).step_by(2) {
+            for pixel in row {
+                let [r, g, b, a] = pixel.to_array();
+                let [r, g, b] = [r, g, b].map(|channel| (channel as f32 * keep) as u8);
+                *pixel = Color32::from_rgba_premultiplied(r, g, b, a);
+            }
+        }
+    }
+}
+
+/// Approximates how the GBC's LCD shows colors, games were made for it and look oversaturated
+/// without this. Works on the 5 bits per channel the GBC actually has.
+pub fn correct_cgb_color(r: u8, g: u8, b: u8) -> [u8; 3] {
+    let (r, g, b) = ((r >> 3) as u32, (g >> 3) as u32, (b >> 3) as u32);
+    let mixed = [
+        r * 26 + g * 4 + b * 2,
+        g * 24 + b * 8,
+        r * 6 + g * 4 + b * 22,
+    ];
+    mixed.map(|channel| (channel.min(960) >> 2) as u8)
+}
+
+//Adjustments applied to every frame on top of the palette
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct ColorAdjust {
+    //Degrees
+    pub hue: f32,
+    //Added to each channel, 0.0 is unchanged
+    pub brightness: f32,
+    //Multiplier around mid grey, 1.0 is unchanged
+    pub contrast: f32,
+}
+
+impl Default for ColorAdjust {
+    fn default() -> Self {
+        Self {
+            hue: 0.0,
+            brightness: 0.0,
+            contrast: 1.0,
+        }
+    }
+}
+
+impl ColorAdjust {
+    pub fn display(&mut self, ui: &mut egui::Ui) {
+        ui.add(
+            egui::Slider::new(&mut self.hue, -180.0..=180.0)
+                .text("Hue")
+                .suffix("°"),
+        );
+        ui.add(egui::Slider::new(&mut self.brightness, -0.5..=0.5).text("Brightness"));
+        ui.add(egui::Slider::new(&mut self.contrast, 0.0..=2.0).text("Contrast"));
+        if ui.button("Reset").clicked() {
+            *self = Self::default();
+        }
+    }
+
+    /// Works out the transform for this frame, `None` when nothing would change so it can be skipped.
+    pub fn transform(&self) -> Option<ColorTransform> {
+        if *self == Self::default() {
+            return None;
+        }
+
+        //Rotates around the grey axis while keeping luminance the same
+        let (sin, cos) = self.hue.to_radians().sin_cos();
+        let hue = [
+            [
+                0.213 + 0.787 * cos - 0.213 * sin,
+                0.715 - 0.715 * cos - 0.715 * sin,
+                0.072 - 0.072 * cos + 0.928 * sin,
+            ],
+            [
+                0.213 - 0.213 * cos + 0.143 * sin,
+                0.715 + 0.285 * cos + 0.140 * sin,
+                0.072 - 0.072 * cos - 0.283 * sin,
+            ],
+            [
+                0.213 - 0.213 * cos - 0.787 * sin,
+                0.715 - 0.715 * cos + 0.715 * sin,
+                0.072 + 0.928 * cos + 0.072 * sin,
+            ],
+        ];
+        Some(ColorTransform {
+            hue,
+            brightness: self.brightness,
+            contrast: self.contrast,
+        })
+    }
+}
+
+pub struct ColorTransform {
+    hue: [[f32; 3]; 3],
+    brightness: f32,
+    contrast: f32,
+}
+
+impl ColorTransform {
+    pub fn apply(&self, rgb: [u8; 3]) -> [u8; 3] {
+        let rgb = rgb.map(|channel| channel as f32 / 255.0);
+        self.hue.map(|row| {
+            let channel = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+            let channel = (channel - 0.5) * self.contrast + 0.5 + self.brightness;
+            (channel.clamp(0.0, 1.0) * 255.0).round() as u8
+        })
+    }
+}
+
+//Nearest neighbour upscale by a whole number
+fn upscale(image: &ColorImage, scale: usize) -> ColorImage {
+    let [width, height] = image.size;
+    let mut pixels = Vec::with_capacity(width * height * scale * scale);
+    for row in image.pixels.chunks_exact(width) {
+        let scaled_row: Vec<_> = row
+            .iter()
+            .flat_map(|pixel| std::iter::repeat(*pixel).take(scale))
+            .collect();
+        for _ in 0..scale {
+            pixels.extend_from_slice(&scaled_row);
+        }
+    }
+    ColorImage {
+        size: [width * scale, height * scale],
+        pixels,
+    }
+}
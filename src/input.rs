@@ -1,39 +1,61 @@
 use std::fmt::Display;
 
-use egui::{Context, Key};
-use gilrs::{Button, GamepadId};
+use egui::{Context, InputState, Key, KeyboardShortcut, Modifiers, ViewportId};
+use gilrs::{Axis, Button, GamepadId};
 use serde::{Deserialize, Serialize};
 
+/// How far a trigger axis has to travel before it counts as pressed.
+/// Depending on the driver triggers rest at either 0.0 or -1.0, so only the far end of the range is
+/// treated as a press.
+const AXIS_THRESHOLD: f32 = 0.5;
+
 pub struct Inputs {
-    pub up: InputType,
-    pub down: InputType,
-    pub left: InputType,
-    pub right: InputType,
-    pub a: InputType,
-    pub b: InputType,
-    pub select: InputType,
-    pub start: InputType,
-    pub gilrs: gilrs::Gilrs,
+    pub up: Bindings,
+    pub down: Bindings,
+    pub left: Bindings,
+    pub right: Bindings,
+    pub a: Bindings,
+    pub b: Bindings,
+    pub select: Bindings,
+    pub start: Bindings,
+    pub gilrs: Option<gilrs::Gilrs>,
     egui_ctx: Context,
+    //The game's own window when it's popped out, keys pressed there count too
+    pub game_viewport: Option<ViewportId>,
+    //Taps seen by poll that the emulator hasn't had a frame to see yet, in pressed_all order
+    latched: [bool; 8],
+}
+
+/// Starts gilrs, returning `None` when gamepads aren't supported so we can run keyboard only
+pub fn init_gilrs() -> Option<gilrs::Gilrs> {
+    match gilrs::Gilrs::new() {
+        Ok(gilrs) => Some(gilrs),
+        Err(err) => {
+            log::warn!("Unable to initialize gamepad support, using keyboard only: {err}");
+            None
+        }
+    }
 }
 
 impl Inputs {
-    pub fn new(gilrs: gilrs::Gilrs, egui_ctx: Context) -> Self {
+    pub fn new(gilrs: Option<gilrs::Gilrs>, egui_ctx: Context) -> Self {
         Inputs {
-            up: InputType::Keyboard(Key::ArrowUp),
-            down: InputType::Keyboard(Key::ArrowDown),
-            left: InputType::Keyboard(Key::ArrowLeft),
-            right: InputType::Keyboard(Key::ArrowRight),
-            a: InputType::Keyboard(Key::Z),
-            b: InputType::Keyboard(Key::A),
-            select: InputType::Keyboard(Key::Q),
-            start: InputType::Keyboard(Key::Enter),
+            up: Bindings::key(Key::ArrowUp),
+            down: Bindings::key(Key::ArrowDown),
+            left: Bindings::key(Key::ArrowLeft),
+            right: Bindings::key(Key::ArrowRight),
+            a: Bindings::key(Key::Z),
+            b: Bindings::key(Key::A),
+            select: Bindings::key(Key::Q),
+            start: Bindings::key(Key::Enter),
             gilrs,
             egui_ctx,
+            game_viewport: None,
+            latched: [false; 8],
         }
     }
 
-    pub fn with_state(gilrs: gilrs::Gilrs, egui_ctx: Context, state: InputsState) -> Self {
+    pub fn with_state(gilrs: Option<gilrs::Gilrs>, egui_ctx: Context, state: InputsState) -> Self {
         let mut inputs = Self::new(gilrs, egui_ctx);
         inputs.load(state);
         inputs
@@ -48,61 +70,185 @@ impl Inputs {
             }
         });
         if let InputType::Keyboard(_) = input_type {
-            self.set_button(gb_button, input_type);
+            self.add_button(gb_button, input_type);
             return true;
         }
         //Check for gampad key presses
-        while let Some(gilrs::Event { id, event, time: _ }) = self.gilrs.next_event() {
-            if let gilrs::EventType::ButtonPressed(button, _code) = event {
-                let input_type = InputType::Gamepad((id, button));
-                self.set_button(gb_button, input_type);
-                return true;
+        let Some(gilrs) = &mut self.gilrs else {
+            return false;
+        };
+        while let Some(gilrs::Event { id, event, time: _ }) = gilrs.next_event() {
+            match event {
+                gilrs::EventType::ButtonPressed(button, _code) => {
+                    self.add_button(gb_button, InputType::Gamepad((id, button)));
+                    return true;
+                }
+                //Some controllers only report their triggers as axes
+                gilrs::EventType::AxisChanged(
+                    axis @ (Axis::LeftZ | Axis::RightZ),
+                    value,
+                    _code,
+                ) if value > AXIS_THRESHOLD => {
+                    self.add_button(gb_button, InputType::GamepadAxis((id, axis)));
+                    return true;
+                }
+                _ => (),
             }
         }
         false
     }
 
     pub fn pressed(&mut self, gb_button: GBButton) -> bool {
+        let keyboard = Keyboard::new(&self.egui_ctx, self.game_viewport);
         match gb_button {
-            GBButton::Up => self.up.pressed(&self.gilrs, &self.egui_ctx),
-            GBButton::Down => self.down.pressed(&self.gilrs, &self.egui_ctx),
-            GBButton::Left => self.left.pressed(&self.gilrs, &self.egui_ctx),
-            GBButton::Right => self.right.pressed(&self.gilrs, &self.egui_ctx),
-            GBButton::A => self.a.pressed(&self.gilrs, &self.egui_ctx),
-            GBButton::B => self.b.pressed(&self.gilrs, &self.egui_ctx),
-            GBButton::Select => self.select.pressed(&self.gilrs, &self.egui_ctx),
-            GBButton::Start => self.start.pressed(&self.gilrs, &self.egui_ctx),
+            GBButton::Up => self.up.pressed(self.gilrs.as_ref(), &keyboard),
+            GBButton::Down => self.down.pressed(self.gilrs.as_ref(), &keyboard),
+            GBButton::Left => self.left.pressed(self.gilrs.as_ref(), &keyboard),
+            GBButton::Right => self.right.pressed(self.gilrs.as_ref(), &keyboard),
+            GBButton::A => self.a.pressed(self.gilrs.as_ref(), &keyboard),
+            GBButton::B => self.b.pressed(self.gilrs.as_ref(), &keyboard),
+            GBButton::Select => self.select.pressed(self.gilrs.as_ref(), &keyboard),
+            GBButton::Start => self.start.pressed(self.gilrs.as_ref(), &keyboard),
             GBButton::None => false,
         }
     }
 
-    pub fn pressed_all(&mut self) -> [bool; 8] {
-        [
-            self.a.pressed(&self.gilrs, &self.egui_ctx),
-            self.b.pressed(&self.gilrs, &self.egui_ctx),
-            self.select.pressed(&self.gilrs, &self.egui_ctx),
-            self.start.pressed(&self.gilrs, &self.egui_ctx),
-            self.right.pressed(&self.gilrs, &self.egui_ctx),
-            self.left.pressed(&self.gilrs, &self.egui_ctx),
-            self.up.pressed(&self.gilrs, &self.egui_ctx),
-            self.down.pressed(&self.gilrs, &self.egui_ctx),
+    //Throws away pending gamepad events, for when nothing is going to act on them
+    pub fn discard_events(&mut self) {
+        if let Some(gilrs) = &mut self.gilrs {
+            while gilrs.next_event().is_some() {}
+        }
+    }
+
+    /// Drains pending gamepad events. With `latch` set any button tapped since the last call is
+    /// held in `pressed_all` until `clear_latched`, so presses shorter than a frame aren't lost.
+    pub fn poll(&mut self, latch: bool) {
+        let mut gamepad_presses = Vec::new();
+        if let Some(gilrs) = &mut self.gilrs {
+            while let Some(gilrs::Event { id, event, time: _ }) = gilrs.next_event() {
+                if let gilrs::EventType::ButtonPressed(button, _code) = event {
+                    gamepad_presses.push((id, button));
+                }
+            }
+        }
+        if !latch {
+            return;
+        }
+
+        let tapped = [
+            &self.a,
+            &self.b,
+            &self.select,
+            &self.start,
+            &self.right,
+            &self.left,
+            &self.up,
+            &self.down,
         ]
+        .map(|bindings| {
+            bindings.tapped(
+                &gamepad_presses,
+                &Keyboard::new(&self.egui_ctx, self.game_viewport),
+            )
+        });
+        for (latched, tapped) in self.latched.iter_mut().zip(tapped) {
+            *latched |= tapped;
+        }
+    }
+
+    pub fn clear_latched(&mut self) {
+        self.latched = [false; 8];
+    }
+
+    pub fn pressed_all(&mut self) -> [bool; 8] {
+        let keyboard = Keyboard::new(&self.egui_ctx, self.game_viewport);
+        let pressed = [
+            self.a.pressed(self.gilrs.as_ref(), &keyboard),
+            self.b.pressed(self.gilrs.as_ref(), &keyboard),
+            self.select.pressed(self.gilrs.as_ref(), &keyboard),
+            self.start.pressed(self.gilrs.as_ref(), &keyboard),
+            self.right.pressed(self.gilrs.as_ref(), &keyboard),
+            self.left.pressed(self.gilrs.as_ref(), &keyboard),
+            self.up.pressed(self.gilrs.as_ref(), &keyboard),
+            self.down.pressed(self.gilrs.as_ref(), &keyboard),
+        ];
+        std::array::from_fn(|i| pressed[i] || self.latched[i])
     }
 
-    pub fn set_button(&mut self, gb_button: GBButton, input: InputType) {
+    pub fn bindings(&mut self, gb_button: GBButton) -> Option<&mut Bindings> {
         match gb_button {
-            GBButton::Up => self.up.set_button(input),
-            GBButton::Down => self.down.set_button(input),
-            GBButton::Left => self.left.set_button(input),
-            GBButton::Right => self.right.set_button(input),
-            GBButton::A => self.a.set_button(input),
-            GBButton::B => self.b.set_button(input),
-            GBButton::Select => self.select.set_button(input),
-            GBButton::Start => self.start.set_button(input),
-            GBButton::None => {}
+            GBButton::Up => Some(&mut self.up),
+            GBButton::Down => Some(&mut self.down),
+            GBButton::Left => Some(&mut self.left),
+            GBButton::Right => Some(&mut self.right),
+            GBButton::A => Some(&mut self.a),
+            GBButton::B => Some(&mut self.b),
+            GBButton::Select => Some(&mut self.select),
+            GBButton::Start => Some(&mut self.start),
+            GBButton::None => None,
         }
     }
 
+    /// What each binding for a button is, using the controller's name when it's connected
+    pub fn binding_labels(&self, gb_button: GBButton) -> Vec<String> {
+        let bindings = match gb_button {
+            GBButton::Up => &self.up,
+            GBButton::Down => &self.down,
+            GBButton::Left => &self.left,
+            GBButton::Right => &self.right,
+            GBButton::A => &self.a,
+            GBButton::B => &self.b,
+            GBButton::Select => &self.select,
+            GBButton::Start => &self.start,
+            GBButton::None => return Vec::new(),
+        };
+        bindings
+            .iter()
+            .map(|input| {
+                let gamepad = |id| {
+                    self.gilrs
+                        .as_ref()
+                        .and_then(|gilrs| gilrs.connected_gamepad(id))
+                        .map(|gamepad| gamepad.name().to_string())
+                };
+                match *input {
+                    InputType::Gamepad((id, button)) => match gamepad(id) {
+                        Some(name) => format!("{name} - {button:#?}"),
+                        None => input.to_string(),
+                    },
+                    InputType::GamepadAxis((id, axis)) => match gamepad(id) {
+                        Some(name) => format!("{name} - {axis:#?}"),
+                        None => input.to_string(),
+                    },
+                    _ => input.to_string(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn add_button(&mut self, gb_button: GBButton, input: InputType) {
+        if let Some(bindings) = self.bindings(gb_button) {
+            bindings.add(input);
+        }
+    }
+
+    //Whether a keyboard key is bound to one of the gameboy buttons
+    pub fn is_bound(&self, key: Key) -> bool {
+        [
+            &self.up,
+            &self.down,
+            &self.left,
+            &self.right,
+            &self.a,
+            &self.b,
+            &self.select,
+            &self.start,
+        ]
+        .iter()
+        .flat_map(|bindings| bindings.0.iter())
+        .any(|input| matches!(input, InputType::Keyboard(bound) if *bound == key))
+    }
+
     pub fn save(&self) -> InputsState {
         InputsState {
             up: self.up.clone(),
@@ -130,73 +276,224 @@ impl Inputs {
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct InputsState {
-    up: InputType,
-    down: InputType,
-    left: InputType,
-    right: InputType,
-    a: InputType,
-    b: InputType,
-    select: InputType,
-    start: InputType,
+    up: Bindings,
+    down: Bindings,
+    left: Bindings,
+    right: Bindings,
+    a: Bindings,
+    b: Bindings,
+    select: Bindings,
+    start: Bindings,
 }
 
 impl Default for InputsState {
     fn default() -> Self {
         Self {
-            up: InputType::Keyboard(Key::ArrowUp),
-            down: InputType::Keyboard(Key::ArrowDown),
-            left: InputType::Keyboard(Key::ArrowLeft),
-            right: InputType::Keyboard(Key::ArrowRight),
-            a: InputType::Keyboard(Key::Z),
-            b: InputType::Keyboard(Key::A),
-            select: InputType::Keyboard(Key::Q),
-            start: InputType::Keyboard(Key::Enter),
+            up: Bindings::key(Key::ArrowUp),
+            down: Bindings::key(Key::ArrowDown),
+            left: Bindings::key(Key::ArrowLeft),
+            right: Bindings::key(Key::ArrowRight),
+            a: Bindings::key(Key::Z),
+            b: Bindings::key(Key::A),
+            select: Bindings::key(Key::Q),
+            start: Bindings::key(Key::Enter),
         }
     }
 }
 
-#[derive(Clone, Serialize, Deserialize)]
+impl InputsState {
+    //Nothing bound, for players past the first so they don't fight over the keyboard
+    pub fn unbound() -> Self {
+        Self {
+            up: Bindings::default(),
+            down: Bindings::default(),
+            left: Bindings::default(),
+            right: Bindings::default(),
+            a: Bindings::default(),
+            b: Bindings::default(),
+            select: Bindings::default(),
+            start: Bindings::default(),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Default)]
+pub enum Player {
+    #[default]
+    One,
+    Two,
+}
+
+//Where key presses come from, the main window plus the game's window when it has its own
+struct Keyboard<'a> {
+    egui_ctx: &'a Context,
+    game_viewport: Option<ViewportId>,
+}
+
+impl<'a> Keyboard<'a> {
+    fn new(egui_ctx: &'a Context, game_viewport: Option<ViewportId>) -> Self {
+        Self {
+            egui_ctx,
+            game_viewport,
+        }
+    }
+
+    fn check(&self, reader: impl Fn(&InputState) -> bool) -> bool {
+        self.egui_ctx.input(&reader)
+            || self
+                .game_viewport
+                .is_some_and(|id| self.egui_ctx.input_for(id, &reader))
+    }
+}
+
+//Everything bound to one gameboy button, any of them being held counts as a press
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(from = "BindingsState")]
+pub struct Bindings(Vec<InputType>);
+
+//Older saves only had a single binding per button
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum BindingsState {
+    Many(Vec<InputType>),
+    One(InputType),
+}
+
+impl From<BindingsState> for Bindings {
+    fn from(state: BindingsState) -> Self {
+        match state {
+            BindingsState::Many(inputs) => Bindings(inputs),
+            BindingsState::One(InputType::None) => Bindings::default(),
+            BindingsState::One(input) => Bindings(vec![input]),
+        }
+    }
+}
+
+impl Bindings {
+    fn key(key: Key) -> Self {
+        Bindings(vec![InputType::Keyboard(key)])
+    }
+
+    fn pressed(&mut self, gilrs: Option<&gilrs::Gilrs>, keyboard: &Keyboard<'_>) -> bool {
+        self.0
+            .iter_mut()
+            .any(|input| input.pressed(gilrs, keyboard))
+    }
+
+    //Whether any binding went down since the last frame, even if it's been released again
+    fn tapped(&self, gamepad_presses: &[(GamepadId, Button)], keyboard: &Keyboard<'_>) -> bool {
+        self.0.iter().any(|input| match input {
+            InputType::Keyboard(key) => keyboard.check(|i| i.key_pressed(*key)),
+            InputType::Gamepad(press) => gamepad_presses.contains(press),
+            InputType::GamepadAxis(_) | InputType::None => false,
+        })
+    }
+
+    pub fn add(&mut self, input: InputType) {
+        if !matches!(input, InputType::None) && !self.0.contains(&input) {
+            self.0.push(input);
+        }
+    }
+
+    pub fn remove(&mut self, index: usize) {
+        if index < self.0.len() {
+            self.0.remove(index);
+        }
+    }
+
+    //Leaves the button unbound, saved as an empty list so the defaults don't come back on load
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &InputType> {
+        self.0.iter()
+    }
+}
+
+#[derive(Clone, PartialEq, Serialize, Deserialize)]
 pub enum InputType {
     Gamepad((GamepadId, Button)),
+    GamepadAxis((GamepadId, Axis)),
     Keyboard(Key),
     None,
 }
 
 impl InputType {
-    fn pressed(&mut self, gilrs: &gilrs::Gilrs, egui_ctx: &Context) -> bool {
+    fn pressed(&mut self, gilrs: Option<&gilrs::Gilrs>, keyboard: &Keyboard<'_>) -> bool {
         match *self {
-            InputType::Gamepad((id, button)) => match &mut gilrs.connected_gamepad(id) {
+            InputType::Gamepad((id, button)) => match gilrs.and_then(|g| g.connected_gamepad(id)) {
                 Some(gamepad) => gamepad.is_pressed(button),
                 None => false,
             },
-            InputType::Keyboard(key) => {
-                let mut pressed = false;
-                egui_ctx.input(|i| pressed = i.key_down(key));
-                pressed
-            }
+            InputType::GamepadAxis((id, axis)) => match gilrs.and_then(|g| g.connected_gamepad(id))
+            {
+                Some(gamepad) => gamepad.value(axis) > AXIS_THRESHOLD,
+                None => false,
+            },
+            InputType::Keyboard(key) => keyboard.check(|i| i.key_down(key)),
             InputType::None => false,
         }
     }
-
-    pub fn set_button(&mut self, button: InputType) {
-        match button {
-            InputType::None => {}
-            _ => *self = button,
-        }
-    }
 }
 
 impl Display for InputType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match *self {
             InputType::Gamepad((id, button)) => write!(f, "Gamepad: {id} - {button:#?}"),
+            InputType::GamepadAxis((id, axis)) => write!(f, "Gamepad: {id} - {axis:#?}"),
             InputType::Keyboard(key) => write!(f, "Keyboard: {key:#?}"),
             InputType::None => write!(f, ""),
         }
     }
 }
 
-#[derive(Clone, Copy, Serialize, Deserialize)]
+//Holding A or B with autofire on presses and releases it over and over
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Autofire {
+    pub a: bool,
+    pub b: bool,
+    //Presses per second
+    pub rate: u32,
+    #[serde(skip)]
+    frames: u64,
+}
+
+impl Autofire {
+    pub const RATES: std::ops::RangeInclusive<u32> = 1..=30;
+    const FRAME_RATE: u32 = 60;
+
+    //Counts emulated frames so the rate follows the game rather than how often we repaint
+    pub fn next_frame(&mut self) {
+        self.frames = self.frames.wrapping_add(1);
+    }
+
+    //Takes the buttons in the order the gameboy expects (A, B, Select, Start, ...)
+    pub fn apply(&self, pressed: &mut [bool; 8]) {
+        let period = (Self::FRAME_RATE / self.rate.clamp(1, Self::FRAME_RATE / 2)) as u64;
+        let released = self.frames % period >= period / 2;
+        if self.a && released {
+            pressed[0] = false;
+        }
+        if self.b && released {
+            pressed[1] = false;
+        }
+    }
+}
+
+impl Default for Autofire {
+    fn default() -> Self {
+        Self {
+            a: false,
+            b: false,
+            rate: 10,
+            frames: 0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Serialize, Deserialize)]
 pub enum GBButton {
     Up,
     Down,
@@ -208,3 +505,93 @@ pub enum GBButton {
     Start,
     None,
 }
+
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Hotkey {
+    ToggleMenu,
+    SwapPalette,
+    Mute,
+    Pause,
+    Screenshot,
+    FrameAdvance,
+}
+
+impl Hotkey {
+    pub const ALL: [Hotkey; 6] = [
+        Hotkey::ToggleMenu,
+        Hotkey::SwapPalette,
+        Hotkey::Mute,
+        Hotkey::Pause,
+        Hotkey::Screenshot,
+        Hotkey::FrameAdvance,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Hotkey::ToggleMenu => "Menu",
+            Hotkey::SwapPalette => "Swap Palette",
+            Hotkey::Mute => "Mute",
+            Hotkey::Pause => "Pause",
+            Hotkey::Screenshot => "Screenshot",
+            Hotkey::FrameAdvance => "Frame advance",
+        }
+    }
+}
+
+//Emulator shortcuts, these are separate from the gameboy buttons so they don't collide with them
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Hotkeys {
+    toggle_menu: KeyboardShortcut,
+    swap_palette: KeyboardShortcut,
+    mute: KeyboardShortcut,
+    pause: KeyboardShortcut,
+    screenshot: KeyboardShortcut,
+    frame_advance: KeyboardShortcut,
+    //Pressing Select and Start together toggles the menu, for controller only setups. Off by default
+    //since plenty of games soft reset on the same buttons
+    pub menu_chord: bool,
+}
+
+impl Hotkeys {
+    pub fn get(&self, hotkey: Hotkey) -> &KeyboardShortcut {
+        match hotkey {
+            Hotkey::ToggleMenu => &self.toggle_menu,
+            Hotkey::SwapPalette => &self.swap_palette,
+            Hotkey::Mute => &self.mute,
+            Hotkey::Pause => &self.pause,
+            Hotkey::Screenshot => &self.screenshot,
+            Hotkey::FrameAdvance => &self.frame_advance,
+        }
+    }
+
+    pub fn set(&mut self, hotkey: Hotkey, shortcut: KeyboardShortcut) {
+        match hotkey {
+            Hotkey::ToggleMenu => self.toggle_menu = shortcut,
+            Hotkey::SwapPalette => self.swap_palette = shortcut,
+            Hotkey::Mute => self.mute = shortcut,
+            Hotkey::Pause => self.pause = shortcut,
+            Hotkey::Screenshot => self.screenshot = shortcut,
+            Hotkey::FrameAdvance => self.frame_advance = shortcut,
+        }
+    }
+
+    //Edge triggered, so holding a key down only fires once
+    pub fn pressed(&self, hotkey: Hotkey, egui_ctx: &Context) -> bool {
+        egui_ctx.input_mut(|i| i.consume_shortcut(self.get(hotkey)))
+    }
+}
+
+impl Default for Hotkeys {
+    fn default() -> Self {
+        Self {
+            toggle_menu: KeyboardShortcut::new(Modifiers::NONE, Key::Escape),
+            swap_palette: KeyboardShortcut::new(Modifiers::NONE, Key::P),
+            mute: KeyboardShortcut::new(Modifiers::NONE, Key::M),
+            pause: KeyboardShortcut::new(Modifiers::NONE, Key::Space),
+            screenshot: KeyboardShortcut::new(Modifiers::NONE, Key::F12),
+            frame_advance: KeyboardShortcut::new(Modifiers::NONE, Key::Period),
+            menu_chord: false,
+        }
+    }
+}
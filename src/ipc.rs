@@ -0,0 +1,117 @@
+//! Line based command listener so other programs (stream decks, macro tools, scripts) can drive
+//! the emulator. Only binds to localhost; see the README for the command set.
+
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream};
+use std::path::PathBuf;
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use crossbeam_channel::{Receiver, Sender};
+
+pub const ADDRESS: &str = "127.0.0.1:7177";
+//Each client gets a thread, this stops a misbehaving script from opening them without limit
+const MAX_CLIENTS: usize = 8;
+
+pub enum Command {
+    Load(PathBuf),
+    ToggleMenu,
+    SwapPalette,
+    ToggleMute,
+    Pause,
+    Resume,
+    Reset,
+    Screenshot,
+    Quit,
+}
+
+pub struct Ipc {
+    receiver: Receiver<Command>,
+}
+
+impl Ipc {
+    pub fn start(egui_ctx: egui::Context) -> Option<Self> {
+        let listener = match TcpListener::bind(ADDRESS) {
+            Ok(listener) => listener,
+            Err(err) => {
+                log::error!("Unable to start command listener on {ADDRESS}: {err}");
+                return None;
+            }
+        };
+        log::info!("Listening for commands on {ADDRESS}");
+
+        let (sender, receiver) = crossbeam_channel::unbounded();
+        let clients = Arc::new(AtomicUsize::new(0));
+        std::thread::spawn(move || {
+            for mut stream in listener.incoming().flatten() {
+                if clients.fetch_add(1, Ordering::Relaxed) >= MAX_CLIENTS {
+                    clients.fetch_sub(1, Ordering::Relaxed);
+                    let _ = writeln!(stream, "error: too many connections");
+                    continue;
+                }
+                let (sender, egui_ctx, clients) =
+                    (sender.clone(), egui_ctx.clone(), clients.clone());
+                std::thread::spawn(move || {
+                    handle_client(stream, sender, egui_ctx);
+                    clients.fetch_sub(1, Ordering::Relaxed);
+                });
+            }
+        });
+
+        Some(Self { receiver })
+    }
+
+    pub fn commands(&self) -> Vec<Command> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+fn handle_client(stream: TcpStream, sender: Sender<Command>, egui_ctx: egui::Context) {
+    let Ok(mut writer) = stream.try_clone() else {
+        return;
+    };
+
+    for line in BufReader::new(stream).lines() {
+        let Ok(line) = line else {
+            break;
+        };
+
+        let reply = match parse(&line) {
+            Ok(command) => {
+                if sender.send(command).is_err() {
+                    break;
+                }
+                // The UI may be idle, make sure it picks the command up
+                egui_ctx.request_repaint();
+                "ok".to_string()
+            }
+            Err(err) => err,
+        };
+
+        if writeln!(writer, "{reply}").is_err() {
+            break;
+        }
+    }
+}
+
+fn parse(line: &str) -> Result<Command, String> {
+    let line = line.trim();
+    let (command, argument) = line.split_once(' ').unwrap_or((line, ""));
+    let argument = argument.trim();
+
+    match command {
+        "load" if !argument.is_empty() => Ok(Command::Load(argument.into())),
+        "load" => Err("error: load needs a path to a ROM".to_string()),
+        "menu" => Ok(Command::ToggleMenu),
+        "palette" => Ok(Command::SwapPalette),
+        "mute" => Ok(Command::ToggleMute),
+        "pause" => Ok(Command::Pause),
+        "resume" => Ok(Command::Resume),
+        "reset" => Ok(Command::Reset),
+        "screenshot" => Ok(Command::Screenshot),
+        "quit" => Ok(Command::Quit),
+        _ => Err(format!("error: unknown command '{command}'")),
+    }
+}
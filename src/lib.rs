@@ -2,7 +2,14 @@
 
 mod app;
 mod palettes;
-pub use app::TemplateApp;
+pub use app::{TemplateApp, APP_NAME};
 mod audio;
+mod display;
 pub mod input;
+#[cfg(all(feature = "ipc", not(target_arch = "wasm32")))]
+mod ipc;
+mod notifications;
+mod rom;
 mod saves;
+mod stats;
+mod touch;
@@ -6,6 +6,42 @@
 fn main() -> eframe::Result {
     env_logger::init(); // Log to stderr (if you run with `RUST_LOG=debug`).
 
+    // `--bench <rom> <frames>` runs the core flat out and reports how fast it went
+    let args: Vec<_> = std::env::args_os().collect();
+    if args.get(1).is_some_and(|arg| arg == "--bench") {
+        let rom = args.get(2).map(std::path::PathBuf::from);
+        let frames = args.get(3).and_then(|frames| frames.to_str()?.parse().ok());
+        let (Some(rom), Some(frames)) = (rom, frames) else {
+            eprintln!("usage: --bench <rom> <frames>");
+            std::process::exit(2);
+        };
+        if let Err(err) = bench(&rom, frames) {
+            eprintln!("Benchmark failed: {err}");
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+
+    copy_old_settings();
+
+    // A ROM to start with, e.g. from "Open with" in a file manager
+    let mut rom_path = None;
+    for arg in args.iter().skip(1) {
+        if arg.to_str().is_some_and(|arg| arg.starts_with('-')) {
+            if arg == "--help" || arg == "-h" {
+                println!("usage: solgb_eframe [rom]\n       solgb_eframe --bench <rom> <frames>");
+                return Ok(());
+            }
+            eprintln!("Unknown option {}, ignoring it", arg.to_string_lossy());
+        } else if rom_path.is_none() {
+            rom_path = Some(std::path::PathBuf::from(arg));
+        }
+    }
+    if let Some(path) = rom_path.as_ref().filter(|path| !path.is_file()) {
+        eprintln!("{} doesn't exist", path.display());
+        std::process::exit(1);
+    }
+
     let native_options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default()
             .with_inner_size([400.0, 300.0])
@@ -18,12 +54,77 @@ fn main() -> eframe::Result {
         ..Default::default()
     };
     eframe::run_native(
-        "eframe template",
+        solgb_eframe::APP_NAME,
         native_options,
-        Box::new(|cc| Ok(Box::new(solgb_eframe::TemplateApp::new(cc)))),
+        Box::new(|cc| {
+            let mut app = solgb_eframe::TemplateApp::new(cc);
+            if let Some(path) = rom_path {
+                app.open_path(path);
+            }
+            Ok(Box::new(app))
+        }),
     )
 }
 
+// Settings used to be kept under the template's name, they're copied over the first time. The old
+// folder can be shared with other apps that never changed the name, so it's left where it is
+#[cfg(not(target_arch = "wasm32"))]
+fn copy_old_settings() {
+    const OLD_APP_NAME: &str = "eframe template";
+    let (Some(old), Some(new)) = (
+        eframe::storage_dir(OLD_APP_NAME),
+        eframe::storage_dir(solgb_eframe::APP_NAME),
+    ) else {
+        return;
+    };
+    let (old, new_file) = (old.join("app.ron"), new.join("app.ron"));
+    if new_file.exists() || !old.exists() {
+        return;
+    }
+    if let Err(err) = std::fs::create_dir_all(&new).and_then(|_| std::fs::copy(&old, &new_file)) {
+        log::error!("Unable to copy settings from {}: {err}", old.display());
+    }
+}
+
+// Nothing is rendered or played, audio is thrown away as soon as it's ready so it can't hold the
+// core back
+#[cfg(not(target_arch = "wasm32"))]
+fn bench(rom: &std::path::Path, frames: u64) -> Result<(), String> {
+    use crossbeam_channel::TryRecvError;
+
+    let rom =
+        std::fs::read(rom).map_err(|err| format!("Unable to read {}: {err}", rom.display()))?;
+    let mut gameboy = solgb::GameboyBuilder::default()
+        .with_rom(&rom)
+        .build()
+        .map_err(|err| format!("Unable to setup gameboy: {err}"))?;
+    gameboy
+        .start()
+        .map_err(|err| format!("Failed to start running emulation: {err}"))?;
+    let audio_control = gameboy.audio_control.clone();
+
+    let start = std::time::Instant::now();
+    let mut received = 0;
+    while received < frames {
+        while audio_control.try_get_audio_buffer().is_ok() {}
+        match gameboy.video_rec.try_recv() {
+            Ok(_) => received += 1,
+            Err(TryRecvError::Empty) => std::thread::yield_now(),
+            Err(TryRecvError::Disconnected) => {
+                return Err(format!("The emulator stopped after {received} frames"))
+            }
+        }
+    }
+    let elapsed = start.elapsed();
+
+    println!(
+        "{}: {frames} frames in {elapsed:.2?}, {:.1} fps",
+        gameboy.rom_info.get_name(),
+        frames as f64 / elapsed.as_secs_f64()
+    );
+    Ok(())
+}
+
 // When compiling to web using trunk:
 #[cfg(target_arch = "wasm32")]
 fn main() {
@@ -0,0 +1,97 @@
+//Short messages shown over everything, for problems the user would otherwise only find in the log
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::rc::Rc;
+use std::time::Duration;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+use web_time::Instant;
+
+//How long a message stays up once it's been drawn
+const SHOW_FOR: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Copy)]
+enum Level {
+    Info,
+    Warn,
+    Error,
+}
+
+struct Notification {
+    level: Level,
+    message: String,
+    //When it was first drawn, so messages pushed while minimized aren't missed
+    shown: Option<Instant>,
+}
+
+#[derive(Clone, Default)]
+pub struct Notifications(Rc<RefCell<VecDeque<Notification>>>);
+
+impl Notifications {
+    pub fn info(&self, message: impl Into<String>) {
+        self.push(Level::Info, message.into());
+    }
+
+    pub fn warn(&self, message: impl Into<String>) {
+        self.push(Level::Warn, message.into());
+    }
+
+    pub fn error(&self, message: impl Into<String>) {
+        self.push(Level::Error, message.into());
+    }
+
+    //Everything shown is logged too, so it's still there after the message goes away
+    fn push(&self, level: Level, message: String) {
+        match level {
+            Level::Info => log::info!("{message}"),
+            Level::Warn => log::warn!("{message}"),
+            Level::Error => log::error!("{message}"),
+        }
+        self.0.borrow_mut().push_back(Notification {
+            level,
+            message,
+            shown: None,
+        });
+    }
+
+    pub fn show(&self, ctx: &egui::Context) {
+        let mut notifications = self.0.borrow_mut();
+        let now = Instant::now();
+        notifications.retain(|notification| {
+            notification
+                .shown
+                .map_or(true, |shown| now - shown <= SHOW_FOR)
+        });
+        if notifications.is_empty() {
+            return;
+        }
+
+        let mut dismissed = None;
+        egui::Area::new(egui::Id::new("notifications"))
+            //Above the slowdown indicator
+            .anchor(egui::Align2::RIGHT_BOTTOM, [-8.0, -40.0])
+            .show(ctx, |ui| {
+                for (index, notification) in notifications.iter_mut().enumerate() {
+                    notification.shown.get_or_insert(now);
+                    egui::Frame::popup(ui.style()).show(ui, |ui| {
+                        ui.horizontal(|ui| {
+                            let visuals = ui.visuals();
+                            let (color, icon) = match notification.level {
+                                Level::Info => (visuals.text_color(), "ℹ"),
+                                Level::Warn => (visuals.warn_fg_color, "⚠"),
+                                Level::Error => (visuals.error_fg_color, "❗"),
+                            };
+                            ui.colored_label(color, format!("{icon} {}", notification.message));
+                            if ui.small_button("✖").clicked() {
+                                dismissed = Some(index);
+                            }
+                        });
+                    });
+                }
+            });
+        if let Some(index) = dismissed {
+            notifications.remove(index);
+        }
+    }
+}
@@ -3,6 +3,7 @@ use egui::{
     Color32,
 };
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 
 pub const PALETTES: [(&str, [[u8; 3]; 4]); 4] = [
     ("Sandy", SANDY),
@@ -39,7 +40,28 @@ pub const BLUE: [[u8; 3]; 4] = [
     [0x3A, 0x3E, 0x98],
 ];
 
+type PaletteSet = [[[u8; 3]; 4]; 3];
+
+//What gets written out when exporting palettes, for sharing them with other people
+#[derive(Serialize, Deserialize)]
+struct PalettePack {
+    name: String,
+    bg: [[u8; 3]; 4],
+    spr1: [[u8; 3]; 4],
+    spr2: [[u8; 3]; 4],
+    #[serde(default)]
+    palettes: BTreeMap<String, PaletteSet>,
+}
+
+//Changes to every game's palette at once, they wait for a confirmation click
+#[derive(Clone, Copy)]
+enum BulkAction {
+    ApplyToAll,
+    ClearAll,
+}
+
 #[derive(Serialize, Deserialize)]
+#[serde(default)]
 pub struct Palettes {
     pub bg: [[u8; 3]; 4],
     pub spr1: [[u8; 3]; 4],
@@ -47,7 +69,23 @@ pub struct Palettes {
     pub window_visible: bool,
     pub custom_name: String,
     multi_palette: bool,
-    custom_palettes: HashMap<String, [[[u8; 3]; 4]; 3]>,
+    custom_palettes: HashMap<String, PaletteSet>,
+    quick_slots: [Option<(String, PaletteSet)>; 2],
+    quick_index: usize,
+    //Palettes picked while a game was running, by rom name
+    rom_palettes: HashMap<String, (String, PaletteSet)>,
+    //What to use when there's no game running, or the game has no palette of its own
+    default_palette: Option<(String, PaletteSet)>,
+    #[serde(skip)]
+    rom_name: Option<String>,
+    #[serde(skip)]
+    confirming: Option<BulkAction>,
+}
+
+impl Default for Palettes {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Palettes {
@@ -60,6 +98,12 @@ impl Palettes {
             custom_name: String::from("custom"),
             multi_palette: false,
             custom_palettes: HashMap::new(),
+            quick_slots: [None, None],
+            quick_index: 0,
+            rom_palettes: HashMap::new(),
+            default_palette: None,
+            rom_name: None,
+            confirming: None,
         }
     }
 
@@ -109,6 +153,22 @@ impl Palettes {
             self.save_palette();
         }
 
+        ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
+            for (slot, label) in ["A", "B"].into_iter().enumerate() {
+                if ui.button(format!("Set {label}")).clicked() {
+                    self.quick_slots[slot] = Some((self.custom_name.clone(), self.current()));
+                }
+                match &self.quick_slots[slot] {
+                    Some((name, _)) => ui.monospace(format!("{name: <12}")),
+                    None => ui.monospace(format!("{: <12}", "-")),
+                };
+            }
+        });
+
+        if !self.rom_palettes.is_empty() {
+            self.display_bulk_actions(ui);
+        }
+
         ui.monospace("Default Palettes");
 
         for (name, palette) in PALETTES {
@@ -130,27 +190,65 @@ impl Palettes {
             });
         }
 
+        if changed {
+            self.remember();
+        }
+
         changed
     }
 
-    fn update_palettes(
-        &mut self,
-        ui: &mut egui::Ui,
-        name: &str,
-        palette: &[[[u8; 3]; 4]; 3],
-    ) -> bool {
+    fn display_bulk_actions(&mut self, ui: &mut egui::Ui) {
+        ui.with_layout(
+            egui::Layout::left_to_right(egui::Align::TOP),
+            |ui| match self.confirming {
+                None => {
+                    if ui.button("Apply to all games").clicked() {
+                        self.confirming = Some(BulkAction::ApplyToAll);
+                    }
+                    if ui.button("Clear all per-game palettes").clicked() {
+                        self.confirming = Some(BulkAction::ClearAll);
+                    }
+                }
+                Some(action) => {
+                    let games = self.rom_palettes.len();
+                    match action {
+                        BulkAction::ApplyToAll => {
+                            ui.label(format!("Use {} for {games} games?", self.custom_name))
+                        }
+                        BulkAction::ClearAll => {
+                            ui.label(format!("Forget the palettes of {games} games?"))
+                        }
+                    };
+                    if ui.button("Confirm").clicked() {
+                        self.apply_bulk_action(action);
+                        self.confirming = None;
+                    }
+                    if ui.button("Cancel").clicked() {
+                        self.confirming = None;
+                    }
+                }
+            },
+        );
+    }
+
+    fn apply_bulk_action(&mut self, action: BulkAction) {
+        match action {
+            BulkAction::ApplyToAll => {
+                let palette = (self.custom_name.clone(), self.current());
+                for saved in self.rom_palettes.values_mut() {
+                    *saved = palette.clone();
+                }
+                //Games without a palette of their own pick up the default
+                self.default_palette = Some(palette);
+            }
+            BulkAction::ClearAll => self.rom_palettes.clear(),
+        }
+    }
+
+    fn update_palettes(&mut self, ui: &mut egui::Ui, name: &str, palette: &PaletteSet) -> bool {
         let mut changed = false;
         if ui.monospace(format!("{name: <16}")).clicked() {
-            if self.multi_palette {
-                self.bg = palette[0];
-                self.spr1 = palette[1];
-                self.spr2 = palette[2];
-            } else {
-                self.bg = palette[0];
-                self.spr1 = palette[0];
-                self.spr2 = palette[0];
-            }
-            self.custom_name = name.into();
+            self.set_palette(name, palette);
             changed = true;
         }
         for colors in palette[0] {
@@ -159,14 +257,131 @@ impl Palettes {
         changed
     }
 
-    fn save_palette(&mut self) {
+    fn set_palette(&mut self, name: &str, palette: &PaletteSet) {
         if self.multi_palette {
-            self.custom_palettes
-                .insert(self.custom_name.clone(), [self.bg, self.spr1, self.spr2]);
+            self.bg = palette[0];
+            self.spr1 = palette[1];
+            self.spr2 = palette[2];
         } else {
-            self.custom_palettes
-                .insert(self.custom_name.clone(), [self.bg, self.bg, self.bg]);
+            self.bg = palette[0];
+            self.spr1 = palette[0];
+            self.spr2 = palette[0];
+        }
+        self.custom_name = name.into();
+    }
+
+    //Puts the palette back exactly how it was saved, including whether it used multiple palettes
+    fn restore(&mut self, name: &str, palette: &PaletteSet) {
+        self.multi_palette = palette[0] != palette[1] || palette[0] != palette[2];
+        [self.bg, self.spr1, self.spr2] = *palette;
+        self.custom_name = name.into();
+    }
+
+    //Keeps the palette for the running game, or as the default when nothing is running
+    fn remember(&mut self) {
+        let palette = (self.custom_name.clone(), self.current());
+        match &self.rom_name {
+            Some(rom_name) => {
+                self.rom_palettes.insert(rom_name.clone(), palette);
+            }
+            None => self.default_palette = Some(palette),
+        }
+    }
+
+    /// Switches to the palette saved for `rom_name`, or the default palette if it doesn't have one.
+    pub fn rom_loaded(&mut self, rom_name: &str) {
+        if self.rom_name.is_none() && self.default_palette.is_none() {
+            self.remember();
+        }
+        self.rom_name = Some(rom_name.into());
+        let saved = self
+            .rom_palettes
+            .get(rom_name)
+            .or(self.default_palette.as_ref())
+            .cloned();
+        if let Some((name, palette)) = saved {
+            self.restore(&name, &palette);
+        }
+    }
+
+    pub fn rom_unloaded(&mut self) {
+        if self.rom_name.take().is_some() {
+            if let Some((name, palette)) = self.default_palette.clone() {
+                self.restore(&name, &palette);
+            }
+        }
+    }
+
+    fn current(&self) -> PaletteSet {
+        if self.multi_palette {
+            [self.bg, self.spr1, self.spr2]
+        } else {
+            [self.bg, self.bg, self.bg]
+        }
+    }
+
+    fn save_palette(&mut self) {
+        self.custom_palettes
+            .insert(self.custom_name.clone(), self.current());
+    }
+
+    /// Cycles through the palettes assigned to the quick swap slots, or the default palettes if no
+    /// slots have been assigned. Returns the name of the palette that was switched to.
+    pub fn swap_palette(&mut self) -> String {
+        let slots: Vec<(String, PaletteSet)> = self.quick_slots.iter().flatten().cloned().collect();
+        let choices = if slots.is_empty() {
+            PALETTES
+                .iter()
+                .map(|(name, palette)| (name.to_string(), [*palette, *palette, *palette]))
+                .collect()
+        } else {
+            slots
+        };
+
+        self.quick_index = (self.quick_index + 1) % choices.len();
+        let (name, palette) = &choices[self.quick_index];
+        self.set_palette(name, palette);
+        self.remember();
+        name.clone()
+    }
+
+    pub fn export(&self) -> Result<Vec<u8>, String> {
+        let pack = PalettePack {
+            name: self.custom_name.clone(),
+            bg: self.bg,
+            spr1: self.spr1,
+            spr2: self.spr2,
+            palettes: self
+                .custom_palettes
+                .iter()
+                .map(|(name, palette)| (name.clone(), *palette))
+                .collect(),
+        };
+        serde_json::to_vec_pretty(&pack).map_err(|err| format!("{err}"))
+    }
+
+    /// Merges the palettes from an exported pack into the custom palettes, names that are already
+    /// taken get a number added to the end. Returns how many palettes were added.
+    pub fn import(&mut self, data: &[u8]) -> Result<usize, String> {
+        let pack: PalettePack = serde_json::from_slice(data).map_err(|err| format!("{err}"))?;
+
+        let mut added = 0;
+        let current = (pack.name, [pack.bg, pack.spr1, pack.spr2]);
+        for (name, palette) in std::iter::once(current).chain(pack.palettes) {
+            //Importing the same pack twice shouldn't double everything up
+            if self.custom_palettes.values().any(|saved| *saved == palette) {
+                continue;
+            }
+            let mut unique = name.clone();
+            let mut suffix = 2;
+            while self.custom_palettes.contains_key(&unique) {
+                unique = format!("{name} ({suffix})");
+                suffix += 1;
+            }
+            self.custom_palettes.insert(unique, palette);
+            added += 1;
         }
+        Ok(added)
     }
 
     pub fn get_u32_palette(&self) -> [[u32; 4]; 3] {
@@ -0,0 +1,152 @@
+//Helpers for reading the cartridge header, for things RomInfo doesn't tell us
+use sha1::{Digest, Sha1};
+use solgb::GameboyType;
+use std::io::{Cursor, Read};
+
+const CGB_FLAG: usize = 0x143;
+const CART_TYPE: usize = 0x147;
+const ROM_SIZE: usize = 0x148;
+const RAM_SIZE: usize = 0x149;
+const TITLE: usize = 0x134;
+const HEADER_CHECKSUM: usize = 0x14D;
+const GLOBAL_CHECKSUM: usize = 0x14E;
+
+const ROM_EXTENSIONS: [&str; 2] = [".gb", ".gbc"];
+
+const DMG_BOOT_ROM_SIZE: usize = 256;
+const CGB_BOOT_ROM_SIZE: usize = 2304;
+//SHA-1 of the official boot ROMs, by model
+const DMG_BOOT_ROMS: [&str; 3] = [
+    "4ed31ec6b0b175bb109c0eb5fd3d193da823339f", //DMG
+    "8bd501e31921e9601788316dbd3ce9833a97bcbc", //DMG0
+    "4e68f9da03c310e84c523654b9026e51f26ce7f0", //MGB
+];
+const CGB_BOOT_ROMS: [&str; 3] = [
+    "1293d68bf9643bc4f36954c1e80e38f39864528d", //CGB
+    "df5a0d2d49de38fbd31cc2aab8e62c8550e655c0", //CGB0
+    "fa5287e24b0fa533b3b5ef2b28a81245346c1a0f", //AGB
+];
+
+/// How many bytes of save ram the cartridge has according to its header, `None` if the header
+/// is missing or has a value we don't know.
+pub fn ram_size(rom: &[u8]) -> Option<usize> {
+    //MBC2 has 512 half bytes built in and always reports 0 in the header
+    if matches!(rom.get(CART_TYPE)?, 0x05 | 0x06) {
+        return Some(512);
+    }
+
+    match rom.get(RAM_SIZE)? {
+        0x00 => Some(0),
+        0x01 => Some(0x800),
+        0x02 => Some(0x2000),
+        0x03 => Some(0x8000),
+        0x04 => Some(0x20000),
+        0x05 => Some(0x10000),
+        _ => None,
+    }
+}
+
+pub fn rom_size(rom: &[u8]) -> Option<usize> {
+    match rom.get(ROM_SIZE)? {
+        size @ 0x00..=0x08 => Some(0x8000 << size),
+        _ => None,
+    }
+}
+
+/// The memory bank controller named by the cartridge type, `None` if we don't know it
+pub fn mapper(rom: &[u8]) -> Option<&'static str> {
+    match rom.get(CART_TYPE)? {
+        0x00 | 0x08 | 0x09 => Some("None"),
+        0x01..=0x03 => Some("MBC1"),
+        0x05 | 0x06 => Some("MBC2"),
+        0x0B..=0x0D => Some("MMM01"),
+        0x0F..=0x13 => Some("MBC3"),
+        0x19..=0x1E => Some("MBC5"),
+        0x20 => Some("MBC6"),
+        0x22 => Some("MBC7"),
+        0xFC => Some("Pocket Camera"),
+        0xFD => Some("TAMA5"),
+        0xFE => Some("HuC3"),
+        0xFF => Some("HuC1"),
+        _ => None,
+    }
+}
+
+pub fn has_rtc(rom: &[u8]) -> bool {
+    matches!(rom.get(CART_TYPE), Some(0x0F | 0x10 | 0xFE))
+}
+
+pub fn cgb_support(rom: &[u8]) -> &'static str {
+    match rom.get(CGB_FLAG) {
+        Some(0xC0) => "GBC only",
+        Some(0x80) => "GBC enhanced",
+        _ => "No",
+    }
+}
+
+/// Whether the header checksum matches, `None` if the ROM is too short to have one. It only
+/// covers a few bytes, so it's cheap enough to check every time a ROM loads.
+pub fn header_checksum_ok(rom: &[u8]) -> Option<bool> {
+    let header = rom.get(TITLE..HEADER_CHECKSUM)?;
+    let checksum = header
+        .iter()
+        .fold(0u8, |sum, byte| sum.wrapping_sub(*byte).wrapping_sub(1));
+    Some(checksum == *rom.get(HEADER_CHECKSUM)?)
+}
+
+/// Whether the checksum over the whole ROM matches. Games still run when it doesn't, and it reads
+/// every byte, so it's only worked out when something shows it.
+pub fn global_checksum_ok(rom: &[u8]) -> Option<bool> {
+    let expected = u16::from_be_bytes([*rom.get(GLOBAL_CHECKSUM)?, *rom.get(GLOBAL_CHECKSUM + 1)?]);
+    //Everything but the global checksum itself
+    let checksum = rom
+        .iter()
+        .enumerate()
+        .filter(|(index, _)| !(GLOBAL_CHECKSUM..=GLOBAL_CHECKSUM + 1).contains(index))
+        .fold(0u16, |sum, (_, byte)| sum.wrapping_add(*byte as u16));
+    Some(checksum == expected)
+}
+
+/// Checks an uploaded boot ROM is the right size for the model. Returns whether it's one of the
+/// official dumps, anything else of the right size is allowed so homebrew boot ROMs still work.
+pub fn check_boot_rom(gb_type: GameboyType, data: &[u8]) -> Result<bool, String> {
+    let (size, known) = match gb_type {
+        GameboyType::DMG => (DMG_BOOT_ROM_SIZE, DMG_BOOT_ROMS),
+        GameboyType::CGB => (CGB_BOOT_ROM_SIZE, CGB_BOOT_ROMS),
+    };
+    if data.len() != size {
+        return Err(format!(
+            "Boot ROM is {} bytes, it should be {size}",
+            data.len()
+        ));
+    }
+
+    let hash: String = Sha1::digest(data)
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    Ok(known.contains(&hash.as_str()))
+}
+
+pub fn is_zip(data: &[u8]) -> bool {
+    data.starts_with(b"PK\x03\x04")
+}
+
+/// Every gameboy ROM in a zip archive along with its name inside the archive
+pub fn unzip_roms(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(data)).map_err(|err| err.to_string())?;
+    let mut roms = Vec::new();
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(|err| err.to_string())?;
+        let name = file.name().to_string();
+        let lowercase = name.to_lowercase();
+        if !file.is_file() || !ROM_EXTENSIONS.iter().any(|ext| lowercase.ends_with(ext)) {
+            continue;
+        }
+        let mut rom = Vec::new();
+        file.read_to_end(&mut rom)
+            .map_err(|err| format!("{name}: {err}"))?;
+        roms.push((name, rom));
+    }
+    Ok(roms)
+}
@@ -1,38 +1,583 @@
+#[cfg(any(target_arch = "wasm32", test))]
 use base64::{engine::general_purpose::STANDARD, Engine as _};
 use solgb::{CartType, GameboyType, RomInfo};
+#[cfg(not(target_arch = "wasm32"))]
+use std::path::PathBuf;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant, SystemTime};
+#[cfg(target_arch = "wasm32")]
+use std::{cell::RefCell, collections::BTreeSet, rc::Rc};
 use std::{
-    collections::BTreeMap,
-    io::{self, Write},
+    collections::{BTreeMap, VecDeque},
+    io::{self, Read, Write},
     sync::{Arc, Mutex},
 };
-use wasm_bindgen::JsCast;
-use web_sys::Storage;
-use web_time::{Duration, Instant};
+#[cfg(target_arch = "wasm32")]
+use wasm_bindgen::{closure::Closure, JsCast, JsValue};
+#[cfg(target_arch = "wasm32")]
+use web_sys::{
+    DomException, IdbCursorWithValue, IdbDatabase, IdbObjectStore, IdbRequest, IdbTransaction,
+    IdbTransactionMode,
+};
+#[cfg(target_arch = "wasm32")]
+use web_time::{Duration, Instant, SystemTime};
 use zip::write::SimpleFileOptions;
 
-use crate::app::{BootRomOptions, Events, CGB_ROM_NAME, DMG_ROM_NAME};
+use crate::app::{
+    BootRomOptions, Events, LastDirs, CGB_ROM_NAME, DMG_ROM_NAME, INTERNAL_PREFIX,
+    RECENT_ROM_PREFIX,
+};
+use crate::notifications::Notifications;
+
+//How many old copies of each save are kept, they're overwritten oldest first
+const BACKUP_COUNT: usize = 3;
+const BACKUP_INTERVAL: Duration = Duration::from_secs(5 * 60);
+//Backups are stored as {name}.bak0, {name}.bak1... with when they were made in {name}.baktimes
+const BACKUP_SUFFIX: &str = ".bak";
+const BACKUP_TIMES_SUFFIX: &str = ".baktimes";
+//How many saves are added to a download all each frame
+const ZIP_FILES_PER_FRAME: usize = 4;
+//What internal keys were called before they shared a prefix, and what they're called now
+const LEGACY_PREFIXES: [(&str, &str); 3] = [
+    ("_DMGBOOTROM", DMG_ROM_NAME),
+    ("_CGBBOOTROM", CGB_ROM_NAME),
+    ("_ROM_", RECENT_ROM_PREFIX),
+];
+//What browsers call running out of local storage, older Firefox has its own name
+#[cfg(target_arch = "wasm32")]
+const QUOTA_ERRORS: [&str; 2] = ["QuotaExceededError", "NS_ERROR_DOM_QUOTA_REACHED"];
+#[cfg(target_arch = "wasm32")]
+const DB_NAME: &str = "solgb";
+#[cfg(target_arch = "wasm32")]
+const DB_VERSION: u32 = 1;
+#[cfg(target_arch = "wasm32")]
+const DB_STORE: &str = "saves";
+
+//Where save ram and bootroms are kept, by name
+trait SaveStorage {
+    fn read(&self, name: &str) -> Option<Vec<u8>>;
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), String>;
+    fn delete(&mut self, name: &str);
+    fn names(&self) -> Vec<String>;
+}
+
+//Browser local storage, everything is base64 encoded since it only holds strings
+#[cfg(target_arch = "wasm32")]
+struct WebStorage(web_sys::Storage);
+
+#[cfg(target_arch = "wasm32")]
+impl WebStorage {
+    fn new() -> Option<Self> {
+        let Some(Some(storage)) = web_sys::window().and_then(|s| s.local_storage().ok()) else {
+            return None;
+        };
+        Some(Self(storage))
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl SaveStorage for WebStorage {
+    fn read(&self, name: &str) -> Option<Vec<u8>> {
+        decode_local(&self.0.get_item(name).ok()??)
+    }
+
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        self.0.set_item(name, &encode_local(data)).map_err(js_error)
+    }
+
+    fn delete(&mut self, name: &str) {
+        let _ = self.0.delete(name);
+    }
+
+    fn names(&self) -> Vec<String> {
+        //eframe keeps its own state in here too
+        let excluded = ["app", "egui_memory_ron"];
+        (0..self.0.length().unwrap_or(0))
+            .filter_map(|i| match self.0.key(i) {
+                Ok(Some(key)) => Some(key),
+                _ => {
+                    log::error!("Unable to get key at storage index: {i}");
+                    None
+                }
+            })
+            .filter(|key| !excluded.contains(&key.as_str()))
+            .collect()
+    }
+}
+
+//IndexedDB can only be used asynchronously, so everything in it is read into memory when it opens
+//and changes are written through. Saves are kept as raw bytes, local storage is only read to move
+//saves from older versions across
+#[cfg(target_arch = "wasm32")]
+struct IndexedDbStorage {
+    cache: Rc<RefCell<BTreeMap<String, Vec<u8>>>>,
+    state: Rc<RefCell<DbState>>,
+    //Watches every write transaction, writes finish after write() has returned so this is the
+    //only place their errors show up
+    on_write: Closure<dyn FnMut(web_sys::Event)>,
+}
+
+#[cfg(target_arch = "wasm32")]
+#[derive(Default)]
+struct DbState {
+    db: Option<IdbDatabase>,
+    //Keys changed before the database finished opening, they're newer than what it has
+    touched: BTreeSet<String>,
+    //The database couldn't be opened, changes go to local storage like they used to
+    failed: bool,
+    //Keys read out of local storage at startup, they're removed from it once the database has them
+    migrating: BTreeSet<String>,
+}
+
+#[cfg(target_arch = "wasm32")]
+impl IndexedDbStorage {
+    fn new(notifications: Notifications) -> Option<Self> {
+        let factory = web_sys::window()?.indexed_db().ok()??;
+        let request = factory.open_with_u32(DB_NAME, DB_VERSION).ok()?;
+
+        //Start with whatever is in local storage so reads work before the database has opened
+        let cache = match WebStorage::new() {
+            Some(local) => local
+                .names()
+                .into_iter()
+                .filter_map(|name| Some((name.clone(), local.read(&name)?)))
+                .collect(),
+            None => BTreeMap::new(),
+        };
+        let state = DbState {
+            migrating: cache.keys().cloned().collect(),
+            ..Default::default()
+        };
+        let storage = Self {
+            cache: Rc::new(RefCell::new(cache)),
+            state: Rc::new(RefCell::new(state)),
+            on_write: write_watcher(notifications),
+        };
+
+        let upgrade_request = request.clone();
+        let on_upgrade = Closure::once_into_js(move |_: web_sys::Event| {
+            let Ok(db) = upgrade_request.result() else {
+                return;
+            };
+            if let Err(err) = db
+                .unchecked_into::<IdbDatabase>()
+                .create_object_store(DB_STORE)
+            {
+                log::error!("Unable to create save store: {}", js_error(err));
+            }
+        });
+        request.set_onupgradeneeded(Some(on_upgrade.unchecked_ref()));
+
+        let (cache, state, success_request) = (
+            storage.cache.clone(),
+            storage.state.clone(),
+            request.clone(),
+        );
+        let on_success =
+            Closure::once_into_js(move |_: web_sys::Event| match success_request.result() {
+                Ok(db) => read_all(db.unchecked_into(), cache, state),
+                Err(err) => {
+                    log::error!("Unable to open save database: {}", js_error(err));
+                    use_local_storage(&cache, &state);
+                }
+            });
+        request.set_onsuccess(Some(on_success.unchecked_ref()));
+
+        let (cache, state) = (storage.cache.clone(), storage.state.clone());
+        let on_error = Closure::once_into_js(move |_: web_sys::Event| {
+            log::error!("Unable to open save database");
+            use_local_storage(&cache, &state);
+        });
+        request.set_onerror(Some(on_error.unchecked_ref()));
+
+        Some(storage)
+    }
+
+    fn change(&mut self, name: &str) -> Result<(), String> {
+        let mut state = self.state.borrow_mut();
+        let state = &mut *state;
+        let data = self.cache.borrow().get(name).cloned();
+        match &state.db {
+            Some(db) => store_change(db, name, data.as_deref(), &self.on_write),
+            None if state.failed => {
+                let mut local = WebStorage::new().ok_or("local storage is unavailable")?;
+                match data {
+                    Some(data) => local.write(name, &data),
+                    None => {
+                        local.delete(name);
+                        Ok(())
+                    }
+                }
+            }
+            None => {
+                state.touched.insert(name.to_string());
+                Ok(())
+            }
+        }
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+impl SaveStorage for IndexedDbStorage {
+    fn read(&self, name: &str) -> Option<Vec<u8>> {
+        self.cache.borrow().get(name).cloned()
+    }
+
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        self.cache
+            .borrow_mut()
+            .insert(name.to_string(), data.to_vec());
+        self.change(name)
+    }
+
+    fn delete(&mut self, name: &str) {
+        self.cache.borrow_mut().remove(name);
+        if let Err(err) = self.change(name) {
+            log::error!("Unable to delete {name}: {err}");
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        self.cache.borrow().keys().cloned().collect()
+    }
+}
+
+//Gives up on the database, anything changed while it was opening goes to local storage instead
+#[cfg(target_arch = "wasm32")]
+fn use_local_storage(cache: &RefCell<BTreeMap<String, Vec<u8>>>, state: &RefCell<DbState>) {
+    log::error!("Saving to local storage instead of the save database");
+    let mut state = state.borrow_mut();
+    state.failed = true;
+    if let Some(mut local) = WebStorage::new() {
+        let cache = cache.borrow();
+        for name in std::mem::take(&mut state.touched) {
+            let result = match cache.get(&name) {
+                Some(data) => local.write(&name, data),
+                None => {
+                    local.delete(&name);
+                    Ok(())
+                }
+            };
+            if let Err(err) = result {
+                log::error!("Unable to save {name}: {err}");
+            }
+        }
+    }
+}
+
+//Reads every save out of the database then brings it and the cache up to date with each other
+#[cfg(target_arch = "wasm32")]
+fn read_all(
+    db: IdbDatabase,
+    cache: Rc<RefCell<BTreeMap<String, Vec<u8>>>>,
+    state: Rc<RefCell<DbState>>,
+) {
+    let request = match db
+        .transaction_with_str(DB_STORE)
+        .and_then(|transaction| transaction.object_store(DB_STORE))
+        .and_then(|store| store.open_cursor())
+    {
+        Ok(request) => request,
+        Err(err) => {
+            log::error!("Unable to read save database: {}", js_error(err));
+            use_local_storage(&cache, &state);
+            return;
+        }
+    };
+
+    let (error_cache, error_state, error_request) = (cache.clone(), state.clone(), request.clone());
+    let on_error = Closure::once_into_js(move |_: web_sys::Event| {
+        let err = error_request.error().ok().flatten();
+        log::error!("Unable to read save database: {}", dom_error(err));
+        use_local_storage(&error_cache, &error_state);
+    });
+    request.set_onerror(Some(on_error.unchecked_ref()));
+
+    let cursor_request = request.clone();
+    let mut stored = BTreeMap::new();
+    let on_row = Closure::wrap(Box::new(move |_: web_sys::Event| {
+        let cursor = cursor_request
+            .result()
+            .ok()
+            .and_then(|cursor| cursor.dyn_into::<IdbCursorWithValue>().ok());
+        match cursor {
+            Some(cursor) => {
+                if let (Some(key), Ok(value)) = (
+                    cursor.key().ok().and_then(|key| key.as_string()),
+                    cursor.value(),
+                ) {
+                    stored.insert(key, js_sys::Uint8Array::new(&value).to_vec());
+                }
+                let _ = cursor.continue_();
+            }
+            //The cursor is null once every row has been read
+            None => opened(&db, &cache, &state, std::mem::take(&mut stored)),
+        }
+    }) as Box<dyn FnMut(web_sys::Event)>);
+    request.set_onsuccess(Some(on_row.as_ref().unchecked_ref()));
+    on_row.forget();
+}
+
+#[cfg(target_arch = "wasm32")]
+fn opened(
+    db: &IdbDatabase,
+    cache: &RefCell<BTreeMap<String, Vec<u8>>>,
+    state: &RefCell<DbState>,
+    stored: BTreeMap<String, Vec<u8>>,
+) {
+    let mut state = state.borrow_mut();
+    let mut cache = cache.borrow_mut();
+    let touched = std::mem::take(&mut state.touched);
+    let migrating = std::mem::take(&mut state.migrating);
+    state.db = Some(db.clone());
+
+    //Anything not in the database yet came from local storage or was changed while it opened.
+    //It all goes in one transaction so the local copies are only removed if every write lands
+    let result = db
+        .transaction_with_str_and_mode(DB_STORE, IdbTransactionMode::Readwrite)
+        .map_err(js_error)
+        .and_then(|transaction| {
+            let store = transaction.object_store(DB_STORE).map_err(js_error)?;
+            for (name, data) in cache.iter() {
+                if touched.contains(name) || !stored.contains_key(name) {
+                    apply_change(&store, name, Some(data))?;
+                }
+            }
+            for name in touched.iter().filter(|name| !cache.contains_key(*name)) {
+                apply_change(&store, name, None)?;
+            }
+            Ok(transaction)
+        });
+    for (name, data) in stored {
+        if !touched.contains(&name) {
+            cache.insert(name, data);
+        }
+    }
+
+    let transaction = match result {
+        Ok(transaction) => transaction,
+        Err(err) => {
+            log::error!("Unable to move saves out of local storage: {err}");
+            return;
+        }
+    };
+    //Every key that was read out of local storage is in the database now, or was deleted since
+    let on_complete = Closure::once_into_js(move |_: web_sys::Event| {
+        if let Some(mut local) = WebStorage::new() {
+            for name in &migrating {
+                local.delete(name);
+            }
+        }
+    });
+    transaction.set_oncomplete(Some(on_complete.unchecked_ref()));
+    //A failed write aborts the whole transaction, so nothing is removed from local storage
+    //Each failed request bubbles up to here, so this can run more than once
+    let on_error = Closure::wrap(Box::new(move |_: web_sys::Event| {
+        log::error!("Unable to move saves out of local storage, keeping them there");
+    }) as Box<dyn FnMut(web_sys::Event)>);
+    transaction.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+    on_error.forget();
+    let on_abort = Closure::once_into_js(move |_: web_sys::Event| {
+        log::error!("Moving saves out of local storage was aborted, keeping them there");
+    });
+    transaction.set_onabort(Some(on_abort.unchecked_ref()));
+}
+
+#[cfg(target_arch = "wasm32")]
+fn store_change(
+    db: &IdbDatabase,
+    name: &str,
+    data: Option<&[u8]>,
+    on_write: &Closure<dyn FnMut(web_sys::Event)>,
+) -> Result<(), String> {
+    let transaction = db
+        .transaction_with_str_and_mode(DB_STORE, IdbTransactionMode::Readwrite)
+        .map_err(js_error)?;
+    let on_write = on_write.as_ref().unchecked_ref();
+    transaction.set_oncomplete(Some(on_write));
+    transaction.set_onerror(Some(on_write));
+    transaction.set_onabort(Some(on_write));
+    let store = transaction.object_store(DB_STORE).map_err(js_error)?;
+    apply_change(&store, name, data)
+}
+
+//Reports write transactions that fail, once until one succeeds again since saves are retried every
+//few seconds. A failed write also aborts its transaction, that only gets logged
+#[cfg(target_arch = "wasm32")]
+fn write_watcher(notifications: Notifications) -> Closure<dyn FnMut(web_sys::Event)> {
+    let mut failing = false;
+    Closure::wrap(Box::new(move |event: web_sys::Event| {
+        let err = match event.type_().as_str() {
+            "complete" => {
+                failing = false;
+                return;
+            }
+            //Errors from a request bubble up to the transaction, the request is the target
+            "error" => event
+                .target()
+                .and_then(|target| target.dyn_into::<IdbRequest>().ok())
+                .and_then(|request| request.error().ok().flatten()),
+            //Running out of space only shows up when the transaction commits
+            _ => event
+                .target()
+                .and_then(|target| target.dyn_into::<IdbTransaction>().ok())
+                .and_then(|transaction| transaction.error()),
+        };
+        let message = format!("Unable to save: {}", dom_error(err));
+        if failing {
+            log::error!("{message}");
+        } else {
+            notifications.error(message);
+        }
+        failing = true;
+    }) as Box<dyn FnMut(web_sys::Event)>)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn apply_change(store: &IdbObjectStore, name: &str, data: Option<&[u8]>) -> Result<(), String> {
+    let key = JsValue::from_str(name);
+    match data {
+        Some(data) => store.put_with_key(&js_sys::Uint8Array::from(data).into(), &key),
+        None => store.delete(&key),
+    }
+    .map(|_| ())
+    .map_err(js_error)
+}
+
+#[cfg(target_arch = "wasm32")]
+fn js_error(err: JsValue) -> String {
+    match err.dyn_ref::<DomException>() {
+        //Browsers only give each site a few megabytes, a handful of big saves can fill it
+        Some(err) if QUOTA_ERRORS.contains(&err.name().as_str()) => {
+            "browser storage is full, delete or download some saves to make room".to_string()
+        }
+        Some(err) => format!("{}: {}", err.name(), err.message()),
+        None => err.as_string().unwrap_or("unknown error".to_string()),
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn dom_error(err: Option<DomException>) -> String {
+    err.map_or("unknown error".to_string(), |err| js_error(err.into()))
+}
+
+#[cfg(any(target_arch = "wasm32", test))]
+fn encode_local(data: &[u8]) -> String {
+    STANDARD.encode(data)
+}
+
+#[cfg(any(target_arch = "wasm32", test))]
+fn decode_local(encoded: &str) -> Option<Vec<u8>> {
+    //Quotes aren't in the base64 alphabet, one pair around the value is from it being stored as JSON
+    let encoded = encoded
+        .strip_prefix('"')
+        .and_then(|encoded| encoded.strip_suffix('"'))
+        .unwrap_or(encoded);
+    STANDARD.decode(encoded).ok()
+}
+
+//A folder of .sav files in the app's data directory
+#[cfg(not(target_arch = "wasm32"))]
+struct NativeStorage(PathBuf);
+
+#[cfg(not(target_arch = "wasm32"))]
+impl NativeStorage {
+    fn new() -> Option<Self> {
+        let Some(dir) = eframe::storage_dir(crate::APP_NAME) else {
+            log::error!("Unable to find a data directory for saves");
+            return None;
+        };
+        let dir = dir.join("saves");
+        if let Err(err) = std::fs::create_dir_all(&dir) {
+            log::error!("Unable to create save directory {}: {err}", dir.display());
+            return None;
+        }
+        Some(Self(dir))
+    }
+
+    fn path(&self, name: &str) -> PathBuf {
+        //Names come from rom headers and the save manager, don't let them leave the directory
+        let name = name.replace(['/', '\\'], "_");
+        self.0.join(format!("{name}.sav"))
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SaveStorage for NativeStorage {
+    fn read(&self, name: &str) -> Option<Vec<u8>> {
+        std::fs::read(self.path(name)).ok()
+    }
+
+    fn write(&mut self, name: &str, data: &[u8]) -> Result<(), String> {
+        std::fs::write(self.path(name), data).map_err(|e| format!("{e}"))
+    }
+
+    fn delete(&mut self, name: &str) {
+        if let Err(err) = std::fs::remove_file(self.path(name)) {
+            log::error!("Unable to delete {name}: {err}");
+        }
+    }
+
+    fn names(&self) -> Vec<String> {
+        let Ok(entries) = std::fs::read_dir(&self.0) else {
+            log::error!("Unable to read save directory {}", self.0.display());
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok())
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "sav"))
+            .filter_map(|path| Some(path.file_stem()?.to_str()?.to_owned()))
+            .collect()
+    }
+}
 
 pub struct Saves {
-    storage: Storage,
+    storage: Box<dyn SaveStorage>,
     last_save: Instant,
+    last_backup: Instant,
     pub save_ram: Arc<Mutex<Vec<u8>>>,
     events: Events,
-    save_data: BTreeMap<String, (String, String)>,
+    notifications: Notifications,
+    //Names of the saves listed in the save manager, with when each of their backups was made
+    save_data: BTreeMap<String, [u64; BACKUP_COUNT]>,
+    //The save being renamed and the name typed in so far
+    renaming: Option<(String, String)>,
+    //A download all being put together
+    zipping: Option<ZipJob>,
+    //Saves from an uploaded zip that would replace a different save, waiting for the user to decide
+    import_conflicts: VecDeque<(String, Vec<u8>)>,
+    apply_all_conflicts: bool,
     rom_info: Option<RomInfo>,
+    //Set while writing the running game's save keeps failing
+    save_failing: bool,
 }
 
 impl Saves {
-    pub fn new(events: Events) -> Option<Self> {
-        let Some(Some(storage)) = web_sys::window().and_then(|s| s.local_storage().ok()) else {
-            return None;
+    pub fn new(events: Events, notifications: Notifications) -> Option<Self> {
+        #[cfg(target_arch = "wasm32")]
+        let mut storage: Box<dyn SaveStorage> = match IndexedDbStorage::new(notifications.clone()) {
+            Some(storage) => Box::new(storage),
+            //Some private browsing modes don't have IndexedDB
+            None => Box::new(WebStorage::new()?),
         };
+        #[cfg(not(target_arch = "wasm32"))]
+        let mut storage: Box<dyn SaveStorage> = Box::new(NativeStorage::new()?);
+        migrate_keys(storage.as_mut());
         Some(Self {
             storage,
             last_save: Instant::now(),
+            last_backup: Instant::now(),
             save_ram: Arc::new(Mutex::new(Vec::new())),
             events,
-            save_data: BTreeMap::default(),
+            notifications,
+            save_data: BTreeMap::new(),
+            renaming: None,
+            zipping: None,
+            import_conflicts: VecDeque::new(),
+            apply_all_conflicts: false,
             rom_info: None,
+            save_failing: false,
         })
     }
 
@@ -40,35 +585,114 @@ impl Saves {
         self.rom_info = rom_info;
     }
 
-    pub fn setup_saveram(&mut self, name: &str) {
-        self.save_ram = if let Ok(Some(encoded)) = self.storage.get_item(name) {
-            let save_ram = STANDARD.decode(encoded).unwrap_or_default();
-            Arc::new(Mutex::new(save_ram))
-        } else {
-            Arc::new(Mutex::new(Vec::new()))
-        };
+    //The stored save ram for a game, it's only used once it's handed to save_ram
+    pub fn read_save_ram(&self, name: &str) -> Arc<Mutex<Vec<u8>>> {
+        let save_ram = self.storage.read(name).unwrap_or_default();
+        Arc::new(Mutex::new(save_ram))
     }
 
     pub fn save_current(&mut self, name: &str) {
         const SAVE_INTERVAL: u64 = 5;
         if self.last_save.elapsed() > Duration::from_secs(SAVE_INTERVAL) {
-            if let Some(rom_info) = &self.rom_info {
-                if !rom_info.is_battery_backed() {
-                    return;
+            self.flush_current(name);
+        }
+    }
+
+    //Writes the save ram out right away instead of waiting for the next save interval
+    pub fn flush_current(&mut self, name: &str) {
+        if let Some(rom_info) = &self.rom_info {
+            if !rom_info.is_battery_backed() {
+                return;
+            }
+        }
+
+        if let Ok(save_ram) = self.save_ram.try_lock() {
+            let save_ram = save_ram.clone();
+            match self.storage.write(name, &save_ram) {
+                Ok(()) => self.save_failing = false,
+                Err(err) => {
+                    //This is retried every few seconds, only tell the user the first time
+                    if self.save_failing {
+                        log::error!("Unable to save {name}: {err}");
+                    } else {
+                        self.notifications
+                            .error(format!("Unable to save {name}: {err}"));
+                    }
+                    self.save_failing = true;
                 }
             }
+            if self.last_backup.elapsed() > BACKUP_INTERVAL {
+                self.backup(name, &save_ram);
+            }
+        }
+        self.last_save = Instant::now();
+    }
+
+    fn backup(&mut self, name: &str, data: &[u8]) {
+        self.last_backup = Instant::now();
+        if data.is_empty() {
+            return;
+        }
+
+        let mut times = backup_times(self.storage.as_ref(), name);
+        let Some((slot, _)) = times.iter().enumerate().min_by_key(|(_, time)| **time) else {
+            return;
+        };
+        if let Err(err) = self
+            .storage
+            .write(&format!("{name}{BACKUP_SUFFIX}{slot}"), data)
+        {
+            log::error!("Unable to back up {name}: {err}");
+            return;
+        }
 
-            if let Ok(save_ram) = &self.save_ram.try_lock() {
-                let encoded = STANDARD.encode(save_ram.to_vec());
-                self.storage.set_item(name, &encoded).unwrap();
+        times[slot] = SystemTime::now()
+            .duration_since(SystemTime::UNIX_EPOCH)
+            .map(|time| time.as_secs())
+            .unwrap_or_default();
+        let times: Vec<u8> = times.iter().flat_map(|time| time.to_le_bytes()).collect();
+        if let Err(err) = self
+            .storage
+            .write(&format!("{name}{BACKUP_TIMES_SUFFIX}"), &times)
+        {
+            log::error!("Unable to record backup time for {name}: {err}");
+        }
+        self.save_data.clear();
+    }
+
+    //Puts a backup back as the main save, including into the running game if it's the one restored
+    fn restore_backup(&mut self, name: &str, slot: usize) {
+        let Some(data) = self.storage.read(&format!("{name}{BACKUP_SUFFIX}{slot}")) else {
+            log::error!("Backup {slot} of {name} is missing");
+            return;
+        };
+        if let Err(err) = self.storage.write(name, &data) {
+            self.notifications
+                .error(format!("Unable to restore {name}: {err}"));
+            return;
+        }
+        let running = self
+            .rom_info
+            .as_ref()
+            .is_some_and(|rom_info| rom_info.get_name() == name);
+        if running {
+            match self.save_ram.try_lock() {
+                Ok(mut save_ram) => *save_ram = data,
+                Err(_) => {
+                    self.notifications.error(format!(
+                        "Unable to restore {name} into the running game, reload it to use the backup"
+                    ));
+                }
             }
-            self.last_save = Instant::now();
         }
     }
 
     pub fn save(&mut self, name: &str, data: &[u8]) {
-        let encoded = STANDARD.encode(data);
-        self.storage.set_item(name, &encoded).unwrap();
+        if let Err(err) = self.storage.write(name, data) {
+            self.notifications
+                .error(format!("Unable to save {name}: {err}"));
+        }
+        self.save_data.clear();
     }
 
     pub fn load_bootrom(
@@ -76,75 +700,138 @@ impl Saves {
         rom_type: &CartType,
         bootrom_options: &BootRomOptions,
     ) -> Option<Vec<u8>> {
-        let mut boot_rom = match (&bootrom_options.gb_type, &rom_type) {
+        if !bootrom_options.use_bootrom {
+            return None;
+        }
+
+        let (name, model) = match (&bootrom_options.gb_type, &rom_type) {
             (None, CartType::DMG)
             | (Some(GameboyType::DMG), CartType::DMG)
             | (Some(GameboyType::DMG), CartType::Hybrid)
-            | (Some(GameboyType::DMG), CartType::CGB) => self.load(DMG_ROM_NAME),
+            | (Some(GameboyType::DMG), CartType::CGB) => (DMG_ROM_NAME, "DMG"),
             (None, CartType::CGB)
             | (None, CartType::Hybrid)
             | (Some(GameboyType::CGB), CartType::DMG)
             | (Some(GameboyType::CGB), CartType::CGB)
-            | (Some(GameboyType::CGB), CartType::Hybrid) => self.load(CGB_ROM_NAME),
+            | (Some(GameboyType::CGB), CartType::Hybrid) => (CGB_ROM_NAME, "CGB"),
         };
 
-        if !bootrom_options.use_bootrom {
-            boot_rom = None;
+        let boot_rom = self.load(name);
+        if boot_rom.is_none() {
+            //Otherwise it looks like the boot animation is just being skipped
+            self.notifications
+                .warn(format!("Boot ROM enabled but none uploaded for {model}"));
         }
-
         boot_rom
     }
 
-    fn load(&mut self, name: &str) -> Option<Vec<u8>> {
-        let encoded = self
+    pub fn load(&mut self, name: &str) -> Option<Vec<u8>> {
+        self.storage.read(name)
+    }
+
+    pub fn delete(&mut self, name: &str) {
+        self.storage.delete(name);
+        self.save_data.clear();
+    }
+
+    //Moves a save and its backups to a new name, nothing is deleted until they've all been written
+    fn rename(&mut self, name: &str, new_name: &str) -> Result<(), String> {
+        if self.storage.read(name).is_none() {
+            return Err(format!("{name} is missing"));
+        }
+
+        let mut keys = vec![(name.to_string(), new_name.to_string())];
+        keys.extend((0..BACKUP_COUNT).map(|slot| {
+            (
+                format!("{name}{BACKUP_SUFFIX}{slot}"),
+                format!("{new_name}{BACKUP_SUFFIX}{slot}"),
+            )
+        }));
+        keys.push((
+            format!("{name}{BACKUP_TIMES_SUFFIX}"),
+            format!("{new_name}{BACKUP_TIMES_SUFFIX}"),
+        ));
+
+        let mut written = Vec::new();
+        for (old, new) in &keys {
+            let Some(data) = self.storage.read(old) else {
+                continue;
+            };
+            if let Err(err) = self.storage.write(new, &data) {
+                for new in written {
+                    self.storage.delete(new);
+                }
+                return Err(err);
+            }
+            written.push(new);
+        }
+        for (old, new) in &keys {
+            if written.contains(&new) {
+                self.storage.delete(old);
+            }
+        }
+        self.save_data.clear();
+        Ok(())
+    }
+
+    //Starts zipping every save, it's built a few saves at a time by continue_download
+    pub fn download_all(&mut self) {
+        if self.zipping.is_some() {
+            return;
+        }
+        let keys = self
             .storage
-            .get_item(name)
-            .unwrap_or(None)
-            .unwrap_or_default();
-        STANDARD.decode(encoded).ok()
+            .names()
+            .into_iter()
+            .filter(|key| !key.starts_with(RECENT_ROM_PREFIX))
+            .collect();
+        self.zipping = Some(ZipJob {
+            keys,
+            done: 0,
+            zip: zip::ZipWriter::new(io::Cursor::new(Vec::new())),
+        });
     }
 
-    pub fn download_all(&mut self) -> Result<(), String> {
-        let cursor = io::Cursor::new(Vec::new());
-        let mut zip = zip::ZipWriter::new(cursor);
+    //Adds the next few saves to the zip and offers it for download once they're all in
+    fn continue_download(&mut self) -> Result<(), String> {
+        let Some(job) = &mut self.zipping else {
+            return Ok(());
+        };
 
         let options = SimpleFileOptions::default()
             .compression_method(zip::CompressionMethod::Stored)
             .unix_permissions(0o755)
             .last_modified_time(zip::DateTime::default_for_write());
 
-        for i in 0..=self.storage.length().unwrap_or(0) {
-            let Ok(Some(key)) = self.storage.key(i) else {
-                log::error!("Unable to get key at storage index: {i}");
-                continue;
-            };
-
-            if let Ok(Some(item)) = self.storage.get(&key) {
-                let item = item.replace("\"", "");
-                match &STANDARD.decode(item) {
-                    Ok(decoded) => {
-                        zip.start_file(format!("{key}.sav").into_boxed_str(), options)
-                            .unwrap_or(());
-                        zip.write_all(decoded).unwrap_or_default();
-                    }
-                    Err(err) => log::error!("{err}"),
+        for key in job.keys.iter().skip(job.done).take(ZIP_FILES_PER_FRAME) {
+            match self.storage.read(key) {
+                Some(data) => {
+                    job.zip
+                        .start_file(format!("{key}.sav").into_boxed_str(), options)
+                        .map_err(|err| format!("{key}: {err}"))?;
+                    job.zip
+                        .write_all(&data)
+                        .map_err(|err| format!("{key}: {err}"))?;
                 }
+                None => log::error!("Unable to read {key}"),
             }
         }
+        job.done = (job.done + ZIP_FILES_PER_FRAME).min(job.keys.len());
+        if job.done < job.keys.len() {
+            return Ok(());
+        }
 
-        let encoded = match zip.finish() {
-            Ok(cursor) => STANDARD.encode(cursor.into_inner()),
-            Err(err) => {
-                return Err(format!("{err}"));
-            }
+        let Some(job) = self.zipping.take() else {
+            return Ok(());
         };
-
-        Saves::download_helper("saves.zip", &encoded)?;
+        let zipped = job.zip.finish().map_err(|err| format!("{err}"))?;
+        crate::app::save_file("saves.zip", ("Zip Archive", &["zip"]), &zipped.into_inner());
 
         Ok(())
     }
 
-    fn download_helper(name: &str, base64_data: &str) -> Result<(), String> {
+    #[cfg(target_arch = "wasm32")]
+    pub(crate) fn download_helper(name: &str, base64_data: &str) -> Result<(), String> {
         if let Err(err) = STANDARD.decode(base64_data) {
             return Err(format!("String is not base64: {err}"));
         }
@@ -167,75 +854,412 @@ impl Saves {
         Ok(())
     }
 
-    pub fn upload(&mut self) {
+    //Writes out the latest save ram for a game first so the export isn't behind
+    pub fn export(&mut self, name: &str) {
+        self.flush_current(name);
+        match self.storage.read(name) {
+            Some(data) => crate::app::save_file(
+                &format!("{name}.sav"),
+                ("Gameboy Save Ram File", &["sav"]),
+                &data,
+            ),
+            None => {
+                self.notifications
+                    .error(format!("No save found for {name}"));
+            }
+        }
+    }
+
+    pub fn upload(&mut self, last_dirs: &mut LastDirs) {
         crate::app::open(
             &self.events,
-            &[("Gameboy Save Ram File", &["sav"]), ("All Files", &["*"])],
+            &[("Gameboy Save Ram File", &["sav"]), crate::app::ALL_FILES],
             crate::app::EventType::SaveUpload,
+            last_dirs,
         );
     }
 
-    pub fn show_save_manager(&mut self, ui: &mut egui::Ui) {
-        let excluded: [String; 4] = [
-            "app".into(),
-            "egui_memory_ron".into(),
-            crate::app::DMG_ROM_NAME.into(),
-            crate::app::CGB_ROM_NAME.into(),
-        ];
-        if self.save_data.is_empty() {
-            for i in 0..=self.storage.length().unwrap_or(0) {
-                let Ok(Some(key)) = self.storage.key(i) else {
-                    log::error!("Unable to get key at storage index: {i}");
-                    continue;
-                };
-                if let Ok(Some(item)) = self.storage.get(&key) {
-                    if !excluded.contains(&key) {
-                        // Ignore egui/app entries
-                        self.save_data.insert(key.clone(), (key, item));
+    pub fn upload_all(&mut self, last_dirs: &mut LastDirs) {
+        crate::app::open(
+            &self.events,
+            &[("Zip Archive", &["zip"]), crate::app::ALL_FILES],
+            crate::app::EventType::SaveZipUpload,
+            last_dirs,
+        );
+    }
+
+    //Stores every save in a zip, the ones that would replace a different save are asked about first
+    pub fn import_zip(&mut self, data: &[u8]) {
+        let saves = match unzip_saves(data) {
+            Ok(saves) => saves,
+            Err(err) => {
+                self.notifications
+                    .error(format!("Unable to read zip file: {err}"));
+                return;
+            }
+        };
+
+        let (mut imported, mut skipped) = (0, 0);
+        for (name, data) in saves {
+            let name = renamed_key(&name).unwrap_or(name);
+            if !looks_like_save(&name, &data) {
+                log::warn!("{name} doesn't look like a save, skipping it");
+                skipped += 1;
+                continue;
+            }
+            match self.storage.read(&name) {
+                Some(existing) if existing == data => (),
+                Some(_) => self.import_conflicts.push_back((name, data)),
+                None => match self.storage.write(&name, &data) {
+                    Ok(()) => imported += 1,
+                    Err(err) => {
+                        log::error!("Unable to save {name}: {err}");
+                        skipped += 1;
                     }
-                };
+                },
             }
         }
+        self.save_data.clear();
+
+        let message = match skipped {
+            0 => format!("Imported {imported} saves"),
+            _ => format!("Imported {imported} saves, skipped {skipped}"),
+        };
+        self.notifications.info(message);
+    }
+
+    pub fn show_import_conflicts(&mut self, ctx: &egui::Context) {
+        let Some((name, _)) = self.import_conflicts.front() else {
+            return;
+        };
 
+        let (mut replace, mut keep) = (false, false);
+        egui::Window::new("Save already exists")
+            .anchor(egui::Align2::CENTER_CENTER, [0.0, 0.0])
+            .collapsible(false)
+            .resizable(false)
+            .show(ctx, |ui| {
+                ui.label(format!(
+                    "There's already a different save called {name}. \
+                     Replace it with the one from the zip?"
+                ));
+                if self.import_conflicts.len() > 1 {
+                    ui.checkbox(
+                        &mut self.apply_all_conflicts,
+                        format!(
+                            "Do the same for the other {}",
+                            self.import_conflicts.len() - 1
+                        ),
+                    );
+                }
+                ui.horizontal(|ui| {
+                    replace = ui.button("Replace").clicked();
+                    keep = ui.button("Keep existing").clicked();
+                });
+            });
+
+        if !replace && !keep {
+            return;
+        }
+        let count = match self.apply_all_conflicts {
+            true => self.import_conflicts.len(),
+            false => 1,
+        };
+        for (name, data) in self.import_conflicts.drain(..count) {
+            if replace {
+                if let Err(err) = self.storage.write(&name, &data) {
+                    self.notifications
+                        .error(format!("Unable to save {name}: {err}"));
+                }
+            }
+        }
+        if self.import_conflicts.is_empty() {
+            self.apply_all_conflicts = false;
+        }
+        self.save_data.clear();
+    }
+
+    pub fn show_save_manager(&mut self, ui: &mut egui::Ui, last_dirs: &mut LastDirs) {
+        if self.save_data.is_empty() {
+            for key in self.storage.names() {
+                if !key.starts_with(INTERNAL_PREFIX) && !is_backup(&key) {
+                    let times = backup_times(self.storage.as_ref(), &key);
+                    self.save_data.insert(key, times);
+                }
+            }
+        }
+
+        let mut action = None;
+        let mut start_rename = None;
+        let mut cancel_rename = false;
         egui::Grid::new("save_manager")
             .min_col_width(0.0)
             .show(ui, |ui| {
-                let mut modified: bool = false;
-                for (key, (key_field, item)) in &mut self.save_data {
+                for (key, times) in &self.save_data {
                     ui.horizontal(|ui| {
                         ui.set_width(200.0);
-                        if ui.text_edit_singleline(key_field).lost_focus() && key != key_field {
-                            let _ = self.storage.set(key_field, item);
-                            let _ = self.storage.delete(key);
-                            modified = true;
-                        };
+                        match &mut self.renaming {
+                            Some((renaming, new_name)) if renaming == key => {
+                                let problem = rename_problem(key, new_name.trim(), &self.save_data);
+                                let response = ui
+                                    .add(egui::TextEdit::singleline(new_name).desired_width(140.0));
+                                let entered = response.lost_focus()
+                                    && ui.input(|i| i.key_pressed(egui::Key::Enter));
+                                let confirmed = ui
+                                    .add_enabled(problem.is_none(), egui::Button::new("✔"))
+                                    .on_disabled_hover_text(problem.unwrap_or_default())
+                                    .clicked();
+                                if problem.is_none() && (confirmed || entered) {
+                                    action = Some(SaveAction::Rename(
+                                        key.clone(),
+                                        new_name.trim().to_string(),
+                                    ));
+                                }
+                                if ui.button("✖").clicked()
+                                    || ui.input(|i| i.key_pressed(egui::Key::Escape))
+                                {
+                                    cancel_rename = true;
+                                }
+                            }
+                            _ => {
+                                ui.label(key);
+                                if ui.small_button("✏").on_hover_text("Rename").clicked() {
+                                    start_rename = Some(key.clone());
+                                }
+                            }
+                        }
                     });
 
                     if ui.button("⬇").clicked() {
-                        let _ = Saves::download_helper(&format!("{key_field}.sav"), item);
+                        if let Some(data) = self.storage.read(key) {
+                            crate::app::save_file(
+                                &format!("{key}.sav"),
+                                ("Gameboy Save Ram File", &["sav"]),
+                                &data,
+                            );
+                        }
                         ui.close_menu();
                     }
 
                     if ui.button("X").clicked() {
-                        let _ = self.storage.delete(key);
-                        modified = true;
+                        action = Some(SaveAction::Delete(key.clone()));
                     };
+
+                    if times.iter().any(|time| *time > 0) {
+                        ui.menu_button("🕓", |ui| {
+                            ui.label("Restore backup");
+                            for (slot, time) in times.iter().enumerate() {
+                                if *time > 0 && ui.button(backup_age(*time)).clicked() {
+                                    action = Some(SaveAction::Restore(key.clone(), slot));
+                                    ui.close_menu();
+                                }
+                            }
+                        });
+                    }
                     ui.end_row();
                 }
-                if modified {
-                    self.save_data.clear();
-                }
             });
 
+        if cancel_rename {
+            self.renaming = None;
+        }
+        if let Some(key) = start_rename {
+            self.renaming = Some((key.clone(), key));
+        }
+        match action {
+            Some(SaveAction::Rename(key, new_name)) => {
+                if key != new_name {
+                    if let Err(err) = self.rename(&key, &new_name) {
+                        self.notifications
+                            .error(format!("Unable to rename {key}: {err}"));
+                    }
+                }
+                self.renaming = None;
+            }
+            Some(SaveAction::Delete(key)) => self.delete(&key),
+            Some(SaveAction::Restore(key, slot)) => self.restore_backup(&key, slot),
+            None => (),
+        }
+
         ui.with_layout(egui::Layout::left_to_right(egui::Align::TOP), |ui| {
             if ui.button("Upload").clicked() {
-                self.upload();
+                self.upload(last_dirs);
             }
-            if ui.button("Download All").clicked() {
-                if let Err(err) = self.download_all() {
-                    log::error!("{err}")
-                }
+            if ui
+                .button("Upload All")
+                .on_hover_text("Import a zip from Download All")
+                .clicked()
+            {
+                self.upload_all(last_dirs);
+            }
+            let zipping = self.zipping.is_some();
+            if ui
+                .add_enabled(!zipping, egui::Button::new("Download All"))
+                .clicked()
+            {
+                self.download_all();
+            }
+            if let Some(job) = &self.zipping {
+                ui.spinner();
+                ui.label(format!(
+                    "Preparing download {}/{}",
+                    job.done,
+                    job.keys.len()
+                ));
             }
         });
+
+        if let Err(err) = self.continue_download() {
+            self.zipping = None;
+            self.notifications
+                .error(format!("Unable to download saves: {err}"));
+        }
+    }
+}
+
+/// The name a key from an older version is stored under now, `None` if it hasn't changed
+pub(crate) fn renamed_key(key: &str) -> Option<String> {
+    LEGACY_PREFIXES
+        .iter()
+        .find_map(|(old, new)| key.strip_prefix(old).map(|rest| format!("{new}{rest}")))
+}
+
+fn migrate_keys(storage: &mut dyn SaveStorage) {
+    for key in storage.names() {
+        let Some(new_key) = renamed_key(&key) else {
+            continue;
+        };
+        let Some(data) = storage.read(&key) else {
+            continue;
+        };
+        match storage.write(&new_key, &data) {
+            Ok(()) => storage.delete(&key),
+            Err(err) => log::error!("Unable to rename {key} to {new_key}: {err}"),
+        }
+    }
+}
+
+//Every save zipped into one download, a few at a time so the UI doesn't freeze. It only moves
+//along while the save manager is open
+struct ZipJob {
+    keys: Vec<String>,
+    //How many of keys are in the zip so far
+    done: usize,
+    zip: zip::ZipWriter<io::Cursor<Vec<u8>>>,
+}
+
+//Every .sav in a zip, by name without the extension
+fn unzip_saves(data: &[u8]) -> Result<Vec<(String, Vec<u8>)>, String> {
+    let mut archive = zip::ZipArchive::new(io::Cursor::new(data)).map_err(|err| err.to_string())?;
+    let mut saves = Vec::new();
+    for index in 0..archive.len() {
+        let mut file = archive.by_index(index).map_err(|err| err.to_string())?;
+        let Some(name) = file.name().strip_suffix(".sav").map(str::to_string) else {
+            continue;
+        };
+        if !file.is_file() {
+            continue;
+        }
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)
+            .map_err(|err| format!("{name}: {err}"))?;
+        saves.push((name, data));
+    }
+    Ok(saves)
+}
+
+//Download All puts boot roms and backup times in the zip too, everything else should be cartridge ram
+fn looks_like_save(name: &str, data: &[u8]) -> bool {
+    const RAM_SIZES: [usize; 6] = [0x200, 0x800, 0x2000, 0x8000, 0x10000, 0x20000];
+    //Emulators that keep the clock for MBC3 games add it on the end
+    const RTC_SIZES: [usize; 3] = [0, 44, 48];
+
+    if name == DMG_ROM_NAME {
+        return crate::rom::check_boot_rom(GameboyType::DMG, data).is_ok();
+    }
+    if name == CGB_ROM_NAME {
+        return crate::rom::check_boot_rom(GameboyType::CGB, data).is_ok();
+    }
+    if name.ends_with(BACKUP_TIMES_SUFFIX) {
+        return data.len() == BACKUP_COUNT * 8;
+    }
+    RAM_SIZES
+        .iter()
+        .any(|ram| RTC_SIZES.iter().any(|rtc| data.len() == ram + rtc))
+}
+
+//What was clicked in the save manager, it's done once the list has finished drawing
+enum SaveAction {
+    Rename(String, String),
+    Delete(String),
+    Restore(String, usize),
+}
+
+//Why a save can't be renamed to new_name, if there's a reason
+fn rename_problem(
+    key: &str,
+    new_name: &str,
+    saves: &BTreeMap<String, [u64; BACKUP_COUNT]>,
+) -> Option<&'static str> {
+    if new_name.is_empty() {
+        Some("The name can't be empty")
+    } else if new_name != key && saves.contains_key(new_name) {
+        Some("There's already a save with that name")
+    } else if new_name.starts_with(INTERNAL_PREFIX) || is_backup(new_name) {
+        Some("That name is reserved")
+    } else {
+        None
+    }
+}
+
+//Seconds since the unix epoch each backup slot was written, 0 for empty slots
+fn backup_times(storage: &dyn SaveStorage, name: &str) -> [u64; BACKUP_COUNT] {
+    let mut times = [0; BACKUP_COUNT];
+    let saved = storage
+        .read(&format!("{name}{BACKUP_TIMES_SUFFIX}"))
+        .unwrap_or_default();
+    for (time, bytes) in times.iter_mut().zip(saved.chunks_exact(8)) {
+        *time = u64::from_le_bytes(bytes.try_into().unwrap_or_default());
+    }
+    times
+}
+
+fn is_backup(key: &str) -> bool {
+    if key.ends_with(BACKUP_TIMES_SUFFIX) {
+        return true;
+    }
+    key.rsplit_once(BACKUP_SUFFIX)
+        .is_some_and(|(_, slot)| !slot.is_empty() && slot.chars().all(|c| c.is_ascii_digit()))
+}
+
+fn backup_age(time: u64) -> String {
+    let now = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .map(|now| now.as_secs())
+        .unwrap_or_default();
+    let minutes = now.saturating_sub(time) / 60;
+    match minutes {
+        0 => "just now".to_string(),
+        1..=59 => format!("{minutes} min ago"),
+        60..=1439 => format!("{} hours ago", minutes / 60),
+        _ => format!("{} days ago", minutes / 1440),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn local_storage_round_trips_quote_bytes() {
+        let data = b"\"save\" \x22\x00\xFF\"".to_vec();
+        assert_eq!(decode_local(&encode_local(&data)), Some(data));
+    }
+
+    #[test]
+    fn local_storage_reads_quoted_base64() {
+        let data = vec![0x22, 0x01, 0x22, 0xAB];
+        let quoted = format!("\"{}\"", encode_local(&data));
+        assert_eq!(decode_local(&quoted), Some(data));
     }
 }
@@ -0,0 +1,156 @@
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+#[cfg(not(target_arch = "wasm32"))]
+use std::time::{Duration, Instant};
+#[cfg(target_arch = "wasm32")]
+use web_time::{Duration, Instant};
+
+//How far back frames are counted when working out the frame rates
+const WINDOW: Duration = Duration::from_secs(1);
+//How many update intervals the frame time graph shows
+const FRAME_TIME_SAMPLES: usize = 120;
+//The top of the graph until something takes longer, two frames at 60hz
+const GRAPH_MIN_MS: f32 = 1000.0 / 30.0;
+const GRAPH_SIZE: [f32; 2] = [240.0, 60.0];
+
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Stats {
+    pub visible: bool,
+    pub graph_visible: bool,
+    //When we got each frame from the emulator
+    #[serde(skip)]
+    frames: VecDeque<Instant>,
+    //When each update ran
+    #[serde(skip)]
+    repaints: VecDeque<Instant>,
+    //How many frames were waiting in video_rec at the last update
+    #[serde(skip)]
+    backlog: usize,
+    #[serde(skip)]
+    last_update: Option<Instant>,
+    //Milliseconds between each of the last few updates, oldest first
+    #[serde(skip)]
+    frame_times: VecDeque<f32>,
+}
+
+impl Stats {
+    //Called at the start of every update, whether or not a game is running
+    pub fn updated(&mut self) {
+        let now = Instant::now();
+        if let Some(last) = self.last_update.replace(now) {
+            if self.frame_times.len() == FRAME_TIME_SAMPLES {
+                self.frame_times.pop_front();
+            }
+            self.frame_times
+                .push_back((now - last).as_secs_f32() * 1000.0);
+        }
+    }
+
+    pub fn repainted(&mut self, backlog: usize) {
+        self.backlog = backlog;
+        record(&mut self.repaints);
+    }
+
+    pub fn frame_received(&mut self) {
+        record(&mut self.frames);
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+        self.backlog = 0;
+    }
+
+    pub fn show(&mut self, ctx: &egui::Context) {
+        if !self.visible && !self.graph_visible {
+            return;
+        }
+
+        //Drop anything that's gone stale since the last time it was recorded
+        let now = Instant::now();
+        self.frames.retain(|time| now - *time <= WINDOW);
+        self.repaints.retain(|time| now - *time <= WINDOW);
+
+        egui::Area::new(egui::Id::new("stats"))
+            .anchor(egui::Align2::LEFT_BOTTOM, [8.0, -8.0])
+            .interactable(false)
+            .show(ctx, |ui| {
+                if self.graph_visible {
+                    self.show_graph(ui);
+                }
+                if self.visible {
+                    ui.label(
+                        egui::RichText::new(format!(
+                            "Emulated: {:.0} fps\nRepaint:  {:.0} fps\nBacklog:  {}",
+                            rate(&self.frames),
+                            rate(&self.repaints),
+                            self.backlog
+                        ))
+                        .monospace(),
+                    );
+                }
+            });
+    }
+
+    //Time between updates as a line, with a guide at 60hz to show hitches against
+    fn show_graph(&self, ui: &mut egui::Ui) {
+        if self.frame_times.is_empty() {
+            return;
+        }
+        let (min, max, total) = self
+            .frame_times
+            .iter()
+            .fold((f32::MAX, 0.0f32, 0.0), |(min, max, total), time| {
+                (min.min(*time), max.max(*time), total + time)
+            });
+        let average = total / self.frame_times.len() as f32;
+
+        let (rect, _) = ui.allocate_exact_size(GRAPH_SIZE.into(), egui::Sense::hover());
+        let painter = ui.painter_at(rect);
+        painter.rect_filled(rect, 0.0, egui::Color32::from_black_alpha(160));
+
+        let top = max.max(GRAPH_MIN_MS);
+        let y = |time: f32| rect.bottom() - time / top * rect.height();
+        let step = rect.width() / (FRAME_TIME_SAMPLES - 1) as f32;
+        let points = self
+            .frame_times
+            .iter()
+            .enumerate()
+            .map(|(index, time)| egui::pos2(rect.left() + index as f32 * step, y(*time)))
+            .collect();
+        let target = y(1000.0 / 60.0);
+        painter.hline(
+            rect.x_range(),
+            target,
+            egui::Stroke::new(1.0, egui::Color32::from_white_alpha(60)),
+        );
+        painter.add(egui::Shape::line(
+            points,
+            egui::Stroke::new(1.0, ui.visuals().warn_fg_color),
+        ));
+
+        ui.label(
+            egui::RichText::new(format!("Frame time: {min:.1} / {average:.1} / {max:.1} ms"))
+                .monospace(),
+        )
+        .on_hover_text("Min / average / max over the graph");
+    }
+}
+
+fn record(times: &mut VecDeque<Instant>) {
+    let now = Instant::now();
+    times.push_back(now);
+    while times.front().is_some_and(|oldest| now - *oldest > WINDOW) {
+        times.pop_front();
+    }
+}
+
+//Events per second over the window
+fn rate(times: &VecDeque<Instant>) -> f32 {
+    match (times.front(), times.back()) {
+        (Some(first), Some(last)) if times.len() > 1 => {
+            (times.len() - 1) as f32 / (*last - *first).as_secs_f32().max(f32::EPSILON)
+        }
+        _ => 0.0,
+    }
+}
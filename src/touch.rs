@@ -0,0 +1,306 @@
+use std::collections::HashMap;
+
+use egui::{Align2, Color32, Context, Image, ImageSource, Pos2, Response, Sense, Ui, Vec2};
+use serde::{Deserialize, Serialize};
+
+//Where each button goes in the array sent to the emulator
+const A: usize = 0;
+const B: usize = 1;
+const SELECT: usize = 2;
+const START: usize = 3;
+const RIGHT: usize = 4;
+const LEFT: usize = 5;
+const UP: usize = 6;
+const DOWN: usize = 7;
+
+//How far overlaid controls sit from the edges of the window
+const MARGIN: f32 = 16.0;
+#[cfg(target_arch = "wasm32")]
+const VIBRATE_MS: u32 = 15;
+
+#[derive(Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Placement {
+    #[default]
+    Below,
+    //Over the sides of the game, for phones in landscape
+    Overlay,
+}
+
+impl Placement {
+    pub const ALL: [Placement; 2] = [Placement::Below, Placement::Overlay];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Placement::Below => "Below",
+            Placement::Overlay => "Overlay",
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(default)]
+pub struct TouchControls {
+    pub placement: Placement,
+    //Multiplier on the default tile size
+    pub scale: f32,
+    pub opacity: f32,
+    //D-pad on the right and A/B on the left
+    pub swap_sides: bool,
+    //Buzz on press where the browser supports it, does nothing on native
+    pub vibrate: bool,
+    #[serde(skip)]
+    last_pressed: [bool; 8],
+    //Fingers currently on the screen, so each control can be held by its own one
+    #[serde(skip)]
+    touches: HashMap<(egui::TouchDeviceId, egui::TouchId), Pos2>,
+}
+
+impl Default for TouchControls {
+    fn default() -> Self {
+        Self {
+            placement: Placement::default(),
+            scale: 1.0,
+            opacity: 1.0,
+            swap_sides: false,
+            vibrate: false,
+            last_pressed: [false; 8],
+            touches: HashMap::new(),
+        }
+    }
+}
+
+impl TouchControls {
+    pub fn display_options(&mut self, ui: &mut Ui) {
+        ui.horizontal(|ui| {
+            ui.monospace("Placement:");
+            for placement in Placement::ALL {
+                ui.radio_value(&mut self.placement, placement, placement.label());
+            }
+        });
+        ui.add(egui::Slider::new(&mut self.scale, 0.5..=2.0).text("Size"));
+        ui.add(egui::Slider::new(&mut self.opacity, 0.1..=1.0).text("Opacity"));
+        ui.checkbox(&mut self.swap_sides, "D-pad on the right");
+        if cfg!(target_arch = "wasm32") {
+            ui.checkbox(&mut self.vibrate, "Vibrate on press");
+        }
+    }
+
+    /// Draws the controls and returns which buttons are held, in the order the emulator takes them
+    pub fn show(&mut self, ctx: &Context, ui: &mut Ui) -> [bool; 8] {
+        let points = self.points(ctx);
+        let held = |response: &Response| points.iter().any(|point| response.rect.contains(*point));
+        let tint = Color32::from_white_alpha((self.opacity.clamp(0.0, 1.0) * 255.0) as u8);
+        let mut pressed = [false; 8];
+
+        match self.placement {
+            Placement::Below => {
+                ui.add_space(16.0);
+                //The d-pad and A/B take up 5 of 6 columns at the default size
+                let tile = ui.available_width() / 6.0 * self.scale;
+                ui.horizontal(|ui| {
+                    ui.spacing_mut().item_spacing = Vec2::ZERO;
+                    let gap = (ui.available_width() - tile * 5.0).max(0.0);
+                    if self.swap_sides {
+                        face_buttons(ui, tile, tint, &held, &mut pressed);
+                        ui.add_space(gap);
+                        dpad(ui, tile, tint, &held, &mut pressed);
+                    } else {
+                        dpad(ui, tile, tint, &held, &mut pressed);
+                        ui.add_space(gap);
+                        face_buttons(ui, tile, tint, &held, &mut pressed);
+                    }
+                });
+                ui.vertical_centered(|ui| {
+                    pressed[SELECT] = held(
+                        &ui.add_sized([ui.available_width(), 0.0], egui::Button::new("Select")),
+                    );
+                    pressed[START] = held(
+                        &ui.add_sized([ui.available_width(), 0.0], egui::Button::new("Start")),
+                    );
+                });
+            }
+            Placement::Overlay => {
+                let tile = ctx.screen_rect().height() / 10.0 * self.scale;
+                let (dpad_side, face_side) = match self.swap_sides {
+                    false => (Align2::LEFT_BOTTOM, Align2::RIGHT_BOTTOM),
+                    true => (Align2::RIGHT_BOTTOM, Align2::LEFT_BOTTOM),
+                };
+                overlay(ctx, "touch_dpad", dpad_side, |ui| {
+                    dpad(ui, tile, tint, &held, &mut pressed)
+                });
+                overlay(ctx, "touch_face", face_side, |ui| {
+                    face_buttons(ui, tile, tint, &held, &mut pressed)
+                });
+                overlay(ctx, "touch_start_select", Align2::CENTER_BOTTOM, |ui| {
+                    ui.horizontal(|ui| {
+                        pressed[SELECT] = held(&ui.button("Select"));
+                        pressed[START] = held(&ui.button("Start"));
+                    });
+                });
+            }
+        }
+
+        let newly_pressed = pressed
+            .iter()
+            .zip(self.last_pressed)
+            .any(|(now, before)| *now && !before);
+        if self.vibrate && newly_pressed {
+            vibrate();
+        }
+        self.last_pressed = pressed;
+        pressed
+    }
+
+    //Everywhere the controls are being pressed this frame
+    fn points(&mut self, ctx: &Context) -> Vec<Pos2> {
+        ctx.input(|i| {
+            for event in &i.events {
+                if let egui::Event::Touch {
+                    device_id,
+                    id,
+                    phase,
+                    pos,
+                    ..
+                } = event
+                {
+                    match phase {
+                        egui::TouchPhase::Start | egui::TouchPhase::Move => {
+                            self.touches.insert((*device_id, *id), *pos);
+                        }
+                        egui::TouchPhase::End | egui::TouchPhase::Cancel => {
+                            self.touches.remove(&(*device_id, *id));
+                        }
+                    }
+                }
+            }
+        });
+
+        let mut points: Vec<_> = self.touches.values().copied().collect();
+        //Mouse presses count too. egui also turns the first touch into the pointer, pressing the
+        //same control twice doesn't matter
+        let mouse = ctx.input(|i| {
+            i.pointer
+                .primary_down()
+                .then(|| i.pointer.interact_pos())
+                .flatten()
+        });
+        points.extend(mouse);
+        points
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn vibrate() {
+    let Some(navigator) = web_sys::window().map(|window| window.navigator()) else {
+        return;
+    };
+    //Not every browser has the vibration API and calling it there throws, it's fine to go without
+    if js_sys::Reflect::has(&navigator, &"vibrate".into()).unwrap_or(false) {
+        navigator.vibrate_with_duration(VIBRATE_MS);
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+fn vibrate() {}
+
+fn overlay(ctx: &Context, id: &str, anchor: Align2, add_contents: impl FnOnce(&mut Ui)) {
+    let offset = [
+        -anchor.x().to_sign() * MARGIN,
+        -anchor.y().to_sign() * MARGIN,
+    ];
+    egui::Area::new(egui::Id::new(id))
+        .anchor(anchor, offset)
+        .show(ctx, |ui| {
+            ui.spacing_mut().item_spacing = Vec2::ZERO;
+            add_contents(ui);
+        });
+}
+
+fn tile(ui: &mut Ui, source: ImageSource<'static>, size: f32, tint: Color32) -> Response {
+    ui.add(
+        Image::new(source)
+            .tint(tint)
+            .fit_to_exact_size(Vec2::splat(size)),
+    )
+}
+
+//The corners press both directions next to them
+fn dpad(
+    ui: &mut Ui,
+    size: f32,
+    tint: Color32,
+    held: &impl Fn(&Response) -> bool,
+    pressed: &mut [bool; 8],
+) {
+    let corner = || egui::include_image!("../assets/TRANS.png");
+    egui::Grid::new("touch_dpad")
+        .spacing([0.0, 0.0])
+        .show(ui, |ui| {
+            let up_left = held(&tile(ui, corner(), size, tint));
+            let up = held(&tile(
+                ui,
+                egui::include_image!("../assets/UP.png"),
+                size,
+                tint,
+            ));
+            let up_right = held(&tile(ui, corner(), size, tint));
+            ui.end_row();
+
+            let left = held(&tile(
+                ui,
+                egui::include_image!("../assets/LEFT.png"),
+                size,
+                tint,
+            ));
+            ui.allocate_exact_size(Vec2::splat(size), Sense::hover());
+            let right = held(&tile(
+                ui,
+                egui::include_image!("../assets/RIGHT.png"),
+                size,
+                tint,
+            ));
+            ui.end_row();
+
+            let down_left = held(&tile(ui, corner(), size, tint));
+            let down = held(&tile(
+                ui,
+                egui::include_image!("../assets/DOWN.png"),
+                size,
+                tint,
+            ));
+            let down_right = held(&tile(ui, corner(), size, tint));
+            ui.end_row();
+
+            pressed[UP] = up_left | up | up_right;
+            pressed[DOWN] = down_left | down | down_right;
+            pressed[LEFT] = up_left | left | down_left;
+            pressed[RIGHT] = up_right | right | down_right;
+        });
+}
+
+fn face_buttons(
+    ui: &mut Ui,
+    size: f32,
+    tint: Color32,
+    held: &impl Fn(&Response) -> bool,
+    pressed: &mut [bool; 8],
+) {
+    //Level with the middle of the d-pad
+    ui.vertical(|ui| {
+        ui.add_space(size);
+        ui.horizontal(|ui| {
+            pressed[B] = held(&tile(
+                ui,
+                egui::include_image!("../assets/B.png"),
+                size,
+                tint,
+            ));
+            pressed[A] = held(&tile(
+                ui,
+                egui::include_image!("../assets/A.png"),
+                size,
+                tint,
+            ));
+        });
+    });
+}